@@ -0,0 +1,172 @@
+use globset::{Glob, GlobMatcher};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::cargo::{self, Package};
+
+// What to put in place of {affected} in test_cmd_template
+pub const PLACEHOLDER: &str = "{affected}";
+
+// Works out what needs testing from the paths that changed
+#[derive(Clone)]
+pub enum Affected {
+    // `affected_map` from the config: globs, relative to the root, to the
+    // arguments that test the files they match
+    Map(PathBuf, Vec<(GlobMatcher, Vec<String>)>),
+    // -p for each workspace member with changes, and every member that
+    // depends on them
+    Cargo(Vec<Package>),
+}
+
+impl Affected {
+    pub fn from_map(root: &Path, map: &BTreeMap<String, String>) -> Result<Affected, String> {
+        let mut rules = vec![];
+        for (pattern, args) in map {
+            let glob = Glob::new(pattern).map_err(|e| e.to_string())?;
+            let args = args.split_ascii_whitespace().map(String::from).collect();
+            rules.push((glob.compile_matcher(), args));
+        }
+        return Ok(Affected::Map(root.to_path_buf(), rules));
+    }
+
+    // The arguments that test `paths`, or None if we can't tell for all of
+    // them and everything should be tested
+    pub fn args(&self, paths: &[PathBuf]) -> Option<Vec<String>> {
+        if paths.is_empty() {
+            return None;
+        }
+        if let Affected::Cargo(packages) = self {
+            let changed = paths
+                .iter()
+                .map(|p| cargo::package_for(packages, p))
+                .collect::<Option<Vec<&Package>>>()?;
+            return Some(
+                cargo::with_dependents(packages, &changed)
+                    .iter()
+                    .flat_map(|p| vec![String::from("-p"), p.name.clone()])
+                    .collect(),
+            );
+        }
+        let mut args: Vec<String> = vec![];
+        for path in paths {
+            let path_args = self.args_for(path)?;
+            for arg in path_args {
+                if !args.contains(&arg) {
+                    args.push(arg);
+                }
+            }
+        }
+        return Some(args);
+    }
+
+    fn args_for(&self, path: &Path) -> Option<Vec<String>> {
+        let (root, rules) = match self {
+            Affected::Map(root, rules) => (root, rules),
+            Affected::Cargo(_) => return None,
+        };
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let matched: Vec<String> = rules
+            .iter()
+            .filter(|(glob, _)| glob.is_match(relative))
+            .flat_map(|(_, args)| args.iter().cloned())
+            .collect();
+        if matched.is_empty() {
+            return None;
+        }
+        return Some(matched);
+    }
+}
+
+// "cargo test {affected}" with ["-p", "core"] is `cargo test -p core`. The
+// placeholder can also be part of a longer word, e.g. "--filter={affected}".
+pub fn expand(template: &str, args: &[String]) -> Option<Command> {
+    let mut words = vec![];
+    for word in template.split_ascii_whitespace() {
+        if word == PLACEHOLDER {
+            words.extend(args.iter().cloned());
+        } else {
+            words.push(word.replace(PLACEHOLDER, &args.join(" ")));
+        }
+    }
+    let mut words = words.into_iter();
+    let mut command = Command::new(words.next()?);
+    command.args(words);
+    return Some(command);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(s: &[&str]) -> Vec<String> {
+        return s.iter().map(|s| s.to_string()).collect();
+    }
+
+    #[test]
+    fn test_map() {
+        let root = PathBuf::from("/home/stuff");
+        let mut map = BTreeMap::new();
+        map.insert(String::from("src/parser/**"), String::from("parser"));
+        map.insert(String::from("src/**"), String::from("smoke"));
+        let affected = Affected::from_map(&root, &map).unwrap();
+
+        assert_eq!(
+            affected.args(&[root.join("src/parser/lex.rs"), root.join("src/main.rs")]),
+            Some(strings(&["smoke", "parser"]))
+        );
+        assert_eq!(
+            affected.args(&[root.join("src/main.rs"), root.join("README.md")]),
+            None
+        );
+        assert_eq!(affected.args(&[]), None);
+    }
+
+    #[test]
+    fn test_cargo() {
+        let affected = Affected::Cargo(vec![
+            Package {
+                name: String::from("app"),
+                dir: PathBuf::from("/ws"),
+                path_dependencies: vec![PathBuf::from("/ws/core")],
+            },
+            Package {
+                name: String::from("core"),
+                dir: PathBuf::from("/ws/core"),
+                path_dependencies: vec![],
+            },
+        ]);
+        assert_eq!(
+            affected.args(&[
+                PathBuf::from("/ws/core/src/lib.rs"),
+                PathBuf::from("/ws/core/src/a.rs")
+            ]),
+            Some(strings(&["-p", "app", "-p", "core"]))
+        );
+        assert_eq!(
+            affected.args(&[PathBuf::from("/ws/src/main.rs")]),
+            Some(strings(&["-p", "app"]))
+        );
+        assert_eq!(affected.args(&[PathBuf::from("/ws/README.md")]), None);
+        assert_eq!(
+            affected.args(&[
+                PathBuf::from("/ws/src/main.rs"),
+                PathBuf::from("/ws/Cargo.lock")
+            ]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_expand() {
+        let args = strings(&["-p", "core"]);
+        let cmd = expand("cargo test {affected} -- --quiet", &args).unwrap();
+        assert_eq!(
+            format!("{:?}", cmd),
+            "\"cargo\" \"test\" \"-p\" \"core\" \"--\" \"--quiet\""
+        );
+        let cmd = expand("jest --filter={affected}", &strings(&["a", "b"])).unwrap();
+        assert_eq!(format!("{:?}", cmd), "\"jest\" \"--filter=a b\"");
+        assert!(expand("", &args).is_none());
+    }
+}
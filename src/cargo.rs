@@ -0,0 +1,175 @@
+use serde_json::Value;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// A package in a Cargo workspace
+#[derive(Debug, Clone, PartialEq)]
+pub struct Package {
+    pub name: String,
+    // Where its Cargo.toml is
+    pub dir: PathBuf,
+    // Where the packages it depends on by path are
+    pub path_dependencies: Vec<PathBuf>,
+}
+
+pub fn is_cargo_project(root: &Path) -> bool {
+    return root.join("Cargo.toml").is_file();
+}
+
+// The packages in the workspace at `root`, or just the one package if it
+// isn't a workspace
+pub fn workspace_members(root: &Path) -> io::Result<Vec<Package>> {
+    let output = Command::new("cargo")
+        .args(["metadata", "--no-deps", "--format-version", "1"])
+        .current_dir(root)
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "cargo metadata failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    return parse_members(&String::from_utf8_lossy(&output.stdout));
+}
+
+fn parse_members(metadata: &str) -> io::Result<Vec<Package>> {
+    let metadata: Value = serde_json::from_str(metadata)?;
+    let members: Vec<&Value> = match metadata["workspace_members"].as_array() {
+        Some(members) => members.iter().collect(),
+        None => vec![],
+    };
+    let packages = metadata["packages"].as_array().cloned().unwrap_or_default();
+    return Ok(packages
+        .iter()
+        .filter(|p| members.contains(&&p["id"]))
+        .filter_map(|p| {
+            let manifest = PathBuf::from(p["manifest_path"].as_str()?);
+            let path_dependencies = p["dependencies"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|d| d["path"].as_str().map(PathBuf::from))
+                .collect();
+            return Some(Package {
+                name: p["name"].as_str()?.to_string(),
+                dir: manifest.parent()?.to_path_buf(),
+                path_dependencies,
+            });
+        })
+        .collect());
+}
+
+// Where a package's targets and build script come from. Anything else in
+// its directory, like a README or Cargo.lock, could affect the whole
+// workspace or nothing at all.
+const SOURCE_DIRS: [&str; 4] = ["src", "tests", "benches", "examples"];
+const SOURCE_FILES: [&str; 2] = ["build.rs", "Cargo.toml"];
+
+// The package `path` belongs to. Packages can be nested, so it's the one
+// closest to the file. None if it isn't one of that package's sources.
+pub fn package_for<'a>(packages: &'a [Package], path: &Path) -> Option<&'a Package> {
+    let package = packages
+        .iter()
+        .filter(|p| path.starts_with(&p.dir))
+        .max_by_key(|p| p.dir.components().count())?;
+    let relative = path.strip_prefix(&package.dir).ok()?;
+    let first = relative.components().next()?.as_os_str().to_str()?;
+    let is_source = if relative.components().count() == 1 {
+        SOURCE_FILES.contains(&first)
+    } else {
+        SOURCE_DIRS.contains(&first)
+    };
+    if !is_source {
+        return None;
+    }
+    return Some(package);
+}
+
+// `changed` and every package in the workspace that depends on them,
+// directly or not, in workspace order
+pub fn with_dependents<'a>(packages: &'a [Package], changed: &[&Package]) -> Vec<&'a Package> {
+    let mut affected: Vec<&PathBuf> = changed.iter().map(|p| &p.dir).collect();
+    loop {
+        let dependents: Vec<&PathBuf> = packages
+            .iter()
+            .filter(|p| !affected.contains(&&p.dir))
+            .filter(|p| p.path_dependencies.iter().any(|d| affected.contains(&d)))
+            .map(|p| &p.dir)
+            .collect();
+        if dependents.is_empty() {
+            break;
+        }
+        affected.extend(dependents);
+    }
+    return packages
+        .iter()
+        .filter(|p| affected.contains(&&p.dir))
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, dir: &str, dependencies: &[&str]) -> Package {
+        return Package {
+            name: name.to_string(),
+            dir: PathBuf::from(dir),
+            path_dependencies: dependencies.iter().map(PathBuf::from).collect(),
+        };
+    }
+
+    #[test]
+    fn test_parse_members() {
+        let metadata = r#"{
+            "packages": [
+                {"name": "app", "id": "app 0.1.0 (path+file:///ws)", "manifest_path": "/ws/Cargo.toml",
+                 "dependencies": [{"name": "core", "path": "/ws/core"}, {"name": "serde"}]},
+                {"name": "core", "id": "core 0.1.0 (path+file:///ws/core)", "manifest_path": "/ws/core/Cargo.toml",
+                 "dependencies": []}
+            ],
+            "workspace_members": ["app 0.1.0 (path+file:///ws)", "core 0.1.0 (path+file:///ws/core)"]
+        }"#;
+        assert_eq!(
+            parse_members(metadata).unwrap(),
+            [
+                package("app", "/ws", &["/ws/core"]),
+                package("core", "/ws/core", &[])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_package_for() {
+        let packages = [package("app", "/ws", &[]), package("core", "/ws/core", &[])];
+        let find = |path: &str| package_for(&packages, Path::new(path)).map(|p| p.name.as_str());
+        assert_eq!(find("/ws/core/src/lib.rs"), Some("core"));
+        assert_eq!(find("/ws/src/main.rs"), Some("app"));
+        assert_eq!(find("/ws/core/tests/it.rs"), Some("core"));
+        assert_eq!(find("/ws/core/build.rs"), Some("core"));
+        assert_eq!(find("/ws/Cargo.toml"), Some("app"));
+        assert_eq!(find("/elsewhere/lib.rs"), None);
+        assert_eq!(find("/ws/README.md"), None);
+        assert_eq!(find("/ws/Cargo.lock"), None);
+        assert_eq!(find("/ws/core/docs/src/a.md"), None);
+    }
+
+    #[test]
+    fn test_with_dependents() {
+        let packages = [
+            package("app", "/ws/app", &["/ws/http"]),
+            package("core", "/ws/core", &[]),
+            package("http", "/ws/http", &["/ws/core"]),
+            package("cli", "/ws/cli", &[]),
+        ];
+        let names = |changed: &[&Package]| -> Vec<String> {
+            return with_dependents(&packages, changed)
+                .iter()
+                .map(|p| p.name.clone())
+                .collect();
+        };
+        assert_eq!(names(&[&packages[1]]), ["app", "core", "http"]);
+        assert_eq!(names(&[&packages[3]]), ["cli"]);
+    }
+}
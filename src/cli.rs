@@ -0,0 +1,1258 @@
+use ::ignore::gitignore::Gitignore;
+use clap::Clap;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+
+use crate::error::TcrError;
+use crate::{
+    affected, cargo, control, env, error, events, executor, git, history, hooks, ignore, keys, log,
+    notifications, orchestrator, parallel, server, slow_tests, stats, status, test_paths,
+    test_results, watcher,
+};
+
+#[derive(Clap)]
+#[clap(version = "0.1", author = "Dan Miller <dan@dmiller.dev>")]
+struct Opts {
+    #[clap(short, long)]
+    config: Option<String>,
+    #[clap(short, long)]
+    root: Option<String>,
+    /// Log debug messages too. TCR_LOG=error|warn|info|debug|trace|off overrides this
+    #[clap(short, long)]
+    verbose: bool,
+    /// Prefix messages with the time
+    #[clap(long)]
+    timestamps: bool,
+    /// Also write messages to this file, timestamped
+    #[clap(long)]
+    log_file: Option<String>,
+    /// How to print what tcr is doing: text, compact (one line per passing phase, trimmed failures) or json (one event per line)
+    #[clap(long, default_value = "text")]
+    format: events::Format,
+    /// Write session statistics to this file as JSON on exit
+    #[clap(long)]
+    stats_json: Option<String>,
+    /// Serve status on this address, e.g. 127.0.0.1:7878: GET /status and an event stream at GET /events
+    #[clap(long)]
+    serve: Option<String>,
+    #[clap(subcommand)]
+    subcmd: Option<SubCommand>,
+}
+
+#[derive(Clap)]
+enum SubCommand {
+    /// Run build, test and commit or revert once against the current tree, then exit
+    Once,
+    /// Instead of watching for changes, run every time Enter is pressed
+    Interactive,
+    /// Undo the last commit made by tcr, keeping its changes in the working tree
+    Undo,
+    /// List past cycles
+    History(HistoryOpts),
+    /// Control a running tcr: pause, resume, run, status, undo or shutdown
+    Ctl(CtlOpts),
+}
+
+#[derive(Clap)]
+struct CtlOpts {
+    command: String,
+}
+
+#[derive(Clap)]
+struct HistoryOpts {
+    /// Only show cycles with this result: committed, build_failed, test_failed, failing_test_kept, nothing_to_commit or sync_failed
+    #[clap(long)]
+    result: Option<String>,
+    /// Only show cycles where a changed path contains this
+    #[clap(long)]
+    path: Option<String>,
+    /// Only show the most recent N cycles
+    #[clap(short = 'n', long)]
+    limit: Option<usize>,
+}
+
+// "ls -al" => Command::new("ls").arg("-al");
+#[allow(clippy::needless_late_init, clippy::iter_nth_zero)]
+fn cmd_from_string(s: String) -> Result<std::process::Command, &'static str> {
+    let mut iter = s.split_ascii_whitespace();
+    let cmd;
+    let first;
+
+    first = iter.nth(0);
+    match first {
+        Some(c) => {
+            cmd = c;
+        }
+        None => {
+            // Not a great err msg...
+            return Err("Expected cmd to not be empty");
+        }
+    }
+
+    let mut command = Command::new(cmd);
+    command.args(iter);
+
+    return Ok(command);
+}
+
+// cmd_from_string for the config field `name`
+fn config_cmd(name: &str, s: String) -> error::Result<std::process::Command> {
+    return cmd_from_string(s).map_err(|e| TcrError::Config(format!("{}: {}", name, e)));
+}
+
+// config_cmd for each of a phase's commands
+fn config_cmds(name: &str, cmds: parallel::Commands) -> error::Result<Vec<std::process::Command>> {
+    let cmds = cmds.into_vec();
+    if cmds.is_empty() {
+        return Err(TcrError::Config(format!("{}: expected a command", name)));
+    }
+    return cmds.into_iter().map(|cmd| config_cmd(name, cmd)).collect();
+}
+
+// Runs a phase's command, or its commands at the same time if it has more
+// than one
+struct CmdRunner {
+    cmds: Vec<std::process::Command>,
+    env: env::PhaseEnv,
+    // Set while watching, so the build and tests can be stopped part way.
+    // The orchestrator arms it while they run.
+    cancel: Option<executor::Cancel>,
+    timeout: Option<std::time::Duration>,
+}
+
+impl CmdRunner {
+    fn output(&self, templates: &[&std::process::Command]) -> io::Result<std::process::Output> {
+        let mut cmds = templates
+            .iter()
+            .map(|template| self.env.command(template))
+            .collect::<io::Result<Vec<_>>>()?;
+        let cancel = match &self.cancel {
+            Some(cancel) => cancel,
+            None if cmds.len() == 1 && self.timeout.is_none() => return cmds[0].output(),
+            None => return parallel::output_all(&mut cmds, None, self.timeout),
+        };
+        return parallel::output_all(&mut cmds, Some(cancel), self.timeout);
+    }
+}
+
+impl orchestrator::Runner for CmdRunner {
+    fn run(&mut self) -> io::Result<std::process::Output> {
+        return self.output(&self.cmds.iter().collect::<Vec<_>>());
+    }
+}
+
+// Runs the phase's commands with {affected} filled in from what changed for
+// those with a template, or the full commands when we can't tell what the
+// changes affect
+struct AffectedRunner<'a> {
+    full: CmdRunner,
+    // One for each of full's commands
+    templates: Vec<Option<String>>,
+    affected: Option<affected::Affected>,
+    changed: env::ChangedFiles,
+    // "Building" or "Testing", for the log
+    what: &'static str,
+    logger: &'a log::Logger,
+}
+
+impl orchestrator::Runner for AffectedRunner<'_> {
+    fn run(&mut self) -> io::Result<std::process::Output> {
+        if let (true, Some(affected)) = (self.templates.iter().any(Option::is_some), &self.affected)
+        {
+            let args = affected.args(&self.changed.paths()).unwrap_or_default();
+            if !args.is_empty() {
+                self.logger
+                    .debug(format!("{} {}", self.what, args.join(" ")));
+                let expanded: Vec<Option<std::process::Command>> = self
+                    .templates
+                    .iter()
+                    .map(|t| t.as_ref().and_then(|t| affected::expand(t, &args)))
+                    .collect();
+                let cmds: Vec<&std::process::Command> = self
+                    .full
+                    .cmds
+                    .iter()
+                    .zip(&expanded)
+                    .map(|(full, expanded)| expanded.as_ref().unwrap_or(full))
+                    .collect();
+                return self.full.output(&cmds);
+            }
+            self.logger.debug(format!("{} everything", self.what));
+        }
+        return self.full.run();
+    }
+}
+
+// Where {affected} comes from: affected_map if there is one, otherwise the
+// Cargo workspace's packages
+fn affected(root: &Path, config: &Config) -> error::Result<Option<affected::Affected>> {
+    if !config.cargo && config.test_cmd_template.is_none() {
+        return Ok(None);
+    }
+    if !config.affected_map.is_empty() {
+        let affected = affected::Affected::from_map(root, &config.affected_map)
+            .map_err(|e| TcrError::Config(format!("affected_map: {}", e)))?;
+        return Ok(Some(affected));
+    }
+    if config.cargo || cargo::is_cargo_project(root) {
+        return cargo_affected(root).map(Some);
+    }
+    return Err(TcrError::Config(String::from(
+        "test_cmd_template needs an affected_map outside of Cargo projects",
+    )));
+}
+
+// The Cargo workspace's packages
+fn cargo_affected(root: &Path) -> error::Result<affected::Affected> {
+    if !cargo::is_cargo_project(root) {
+        return Err(TcrError::Config(format!(
+            "cargo: no Cargo.toml in {}",
+            root.display()
+        )));
+    }
+    let members = cargo::workspace_members(root).map_err(|e| TcrError::Config(e.to_string()))?;
+    return Ok(affected::Affected::Cargo(members));
+}
+
+// The cargo subcommands that take -p
+const CARGO_SUBCOMMANDS: [&str; 5] = ["build", "check", "clippy", "test", "bench"];
+
+// `cmd` with {affected} after the cargo subcommand, keeping its flags, e.g.
+// "cargo clippy {affected} -- -D warnings". None if it isn't one of those.
+fn cargo_template(cmd: &std::process::Command) -> Option<String> {
+    if cmd.get_program() != "cargo" {
+        return None;
+    }
+    let args: Vec<String> = cmd
+        .get_args()
+        .map(|a| a.to_string_lossy().to_string())
+        .collect();
+    let sub = args
+        .iter()
+        .position(|a| !a.starts_with('-') && !a.starts_with('+'))?;
+    if !CARGO_SUBCOMMANDS.contains(&args[sub].as_str()) {
+        return None;
+    }
+    let mut words = vec![String::from("cargo")];
+    words.extend(args[..=sub].iter().cloned());
+    words.push(String::from(affected::PLACEHOLDER));
+    words.extend(args[sub + 1..].iter().cloned());
+    return Some(words.join(" "));
+}
+
+// The template for each of a phase's commands, if it has one. A configured
+// template takes the place of the only command, or the first one running
+// the same program. With `cargo` the cargo commands get one each. The
+// phase's other commands always run as they are.
+fn templates(
+    name: &str,
+    cmds: &[std::process::Command],
+    configured: &Option<String>,
+    cargo: bool,
+) -> error::Result<Vec<Option<String>>> {
+    let mut templates = vec![None; cmds.len()];
+    if let Some(t) = configured {
+        if !t.contains(affected::PLACEHOLDER) {
+            return Err(TcrError::Config(format!(
+                "{}: expected {} in it",
+                name,
+                affected::PLACEHOLDER
+            )));
+        }
+        let program = t.split_ascii_whitespace().next().unwrap_or("");
+        let replaces = match cmds.len() {
+            1 => Some(0),
+            _ => cmds.iter().position(|c| c.get_program() == program),
+        };
+        match replaces {
+            Some(i) => templates[i] = Some(t.clone()),
+            None => {
+                return Err(TcrError::Config(format!(
+                    "{}: none of the commands run {}",
+                    name, program
+                )))
+            }
+        }
+    } else if cargo {
+        templates = cmds.iter().map(cargo_template).collect();
+    }
+    return Ok(templates);
+}
+
+// Remembers how the current cycle's test run went, so the commit can
+// mention it. Forgotten once the cycle is over, so a commit made with `c`
+// doesn't claim tests that never ran on its changes.
+#[derive(Clone, Default)]
+struct LastTestResults(Rc<RefCell<Option<test_results::TestResults>>>);
+
+impl events::Reporter for LastTestResults {
+    fn report(&mut self, event: &events::TcrEvent) {
+        match event {
+            events::TcrEvent::CycleStarted { .. }
+            | events::TcrEvent::Committed
+            | events::TcrEvent::CycleFinished { .. }
+            | events::TcrEvent::PhaseStarted {
+                phase: orchestrator::Phase::Test,
+            } => *self.0.borrow_mut() = None,
+            events::TcrEvent::PhaseFinished {
+                phase: orchestrator::Phase::Test,
+                tests,
+                ..
+            } => *self.0.borrow_mut() = tests.clone(),
+            _ => {}
+        }
+    }
+}
+
+// Runs the commit command, then marks whatever it committed as made by tcr
+struct CommitRunner {
+    cmd: std::process::Command,
+    env: env::PhaseEnv,
+    root: PathBuf,
+    tests: LastTestResults,
+}
+
+impl orchestrator::Runner for CommitRunner {
+    fn run(&mut self) -> io::Result<std::process::Output> {
+        let before = git::head(&self.root).ok();
+        let output = self.env.command(&self.cmd)?.output()?;
+        if output.status.success() && git::head(&self.root).ok() != before {
+            let trailers: Vec<String> = match &*self.tests.0.borrow() {
+                Some(t) => vec![format!("Tcr-Tests: {}", t)],
+                None => vec![],
+            };
+            git::add_trailers(&self.root, &trailers)?;
+        }
+        return Ok(output);
+    }
+}
+
+// Skips .git, editor files, anything in .gitignore and the files tcr itself
+// writes to
+fn checker(root: &Path, config: &Config, logger: &log::Logger) -> ignore::Checker {
+    let gitignore_path = root.join(".gitignore");
+    // TODO should I handle the error here? Weird syntax.
+    let (gitignore, _) = Gitignore::new(&gitignore_path);
+    let mut checker = ignore::Checker::new(root.to_path_buf(), Some(gitignore));
+    let own_files = [
+        history_path(root, config),
+        config.test_report.as_ref().map(|p| root.join(p)),
+        logger.file_path().map(|p| p.to_path_buf()),
+    ];
+    for path in own_files.iter().flatten() {
+        checker.ignore_file(path.clone());
+    }
+    return checker;
+}
+
+// Builds an orchestrator for `config` and hands it to `f`. With `cancel`
+// the build and tests can be cancelled while they run.
+fn with_orchestrator<T>(
+    root: PathBuf,
+    config: Config,
+    frontend: &Frontend,
+    logger: &log::Logger,
+    cancel: Option<executor::Cancel>,
+    f: impl FnOnce(&mut orchestrator::Orchestrator) -> T,
+) -> error::Result<T> {
+    let history_path = history_path(&root, &config);
+    let checker = checker(&root, &config, logger);
+    let affected = affected(&root, &config)?;
+    let build_cmds = config_cmds("build_cmd", config.build_cmd)?;
+    let build_templates = templates("build_cmd", &build_cmds, &None, config.cargo)?;
+    // affected_map's arguments are for the tests, the build always goes by
+    // the workspace's packages
+    let build_affected = match &affected {
+        Some(affected::Affected::Cargo(_)) => affected.clone(),
+        _ if config.cargo => Some(cargo_affected(&root)?),
+        _ => None,
+    };
+    let test_cmds = config_cmds("test_cmd", config.test_cmd)?;
+    let test_templates = templates(
+        "test_cmd_template",
+        &test_cmds,
+        &config.test_cmd_template,
+        config.cargo,
+    )?;
+    let timeout = config.timeout_secs.map(std::time::Duration::from_secs);
+    let changed_files = env::ChangedFiles::default();
+    // A missing or malformed env_file is a bad config, not a failing phase
+    let phase_env = |name, phase, config| -> error::Result<env::PhaseEnv> {
+        let env = env::PhaseEnv::new(&root, phase, config, changed_files.clone());
+        env.check()
+            .map_err(|e| TcrError::Config(format!("{}: {}", name, e)))?;
+        return Ok(env);
+    };
+    let builder = &mut AffectedRunner {
+        full: CmdRunner {
+            cmds: build_cmds,
+            env: phase_env("build", orchestrator::Phase::Build, config.build)?,
+            cancel: cancel.clone(),
+            timeout,
+        },
+        templates: build_templates,
+        affected: build_affected,
+        changed: changed_files.clone(),
+        what: "Building",
+        logger,
+    };
+    let last_test_results = LastTestResults::default();
+    let committer = &mut CommitRunner {
+        cmd: config_cmd("commit_cmd", config.commit_cmd)?,
+        env: phase_env("commit", orchestrator::Phase::Commit, config.commit)?,
+        root: root.clone(),
+        tests: last_test_results.clone(),
+    };
+    let tester = &mut AffectedRunner {
+        full: CmdRunner {
+            cmds: test_cmds,
+            env: phase_env("test", orchestrator::Phase::Test, config.test)?,
+            cancel: cancel.clone(),
+            timeout,
+        },
+        templates: test_templates,
+        affected,
+        changed: changed_files.clone(),
+        what: "Testing",
+        logger,
+    };
+    let reverter = &mut CmdRunner {
+        cmds: vec![config_cmd("revert_cmd", config.revert_cmd)?],
+        env: phase_env("revert", orchestrator::Phase::Revert, config.revert)?,
+        cancel: None,
+        timeout: None,
+    };
+
+    let mut path_reverter = git::GitPathReverter::new(root.clone());
+    let mut working_tree = git::GitWorkingTree::new(root.clone());
+    // Declared before the orchestrator so they outlive it
+    let mut history;
+    let mut remote;
+
+    let mut orc =
+        orchestrator::Orchestrator::new(checker, builder, tester, committer, reverter, logger);
+    let failure_log = git_dir(&root).map(|dir| dir.join("tcr-failure.log"));
+    orc.set_reporter(frontend.format.reporter(logger, failure_log));
+    orc.add_reporter(Box::new(frontend.tracker.clone()));
+    orc.add_reporter(Box::new(last_test_results));
+    orc.add_reporter(Box::new(changed_files));
+    orc.set_working_tree(&mut working_tree);
+    if let Some(cancel) = cancel {
+        orc.set_cancel(cancel);
+    }
+    if let Some(path) = config.test_report {
+        orc.set_test_report(root.join(path));
+    }
+    if let Some(server) = &frontend.server {
+        orc.add_reporter(Box::new(server.clone()));
+    }
+    let hook_commands: std::collections::HashMap<_, _> = config
+        .hooks
+        .into_commands()
+        .into_iter()
+        .map(|(hook, cmd)| Ok((hook, config_cmd("hooks", cmd)?)))
+        .collect::<error::Result<_>>()?;
+    if !hook_commands.is_empty() {
+        orc.add_reporter(Box::new(hooks::Hooks::new(&root, hook_commands, logger)));
+    }
+    let notify = config.notifications;
+    if notify.bell || notify.escape.is_some() || notify.notify_cmd.is_some() {
+        let cmd = match notify.notify_cmd {
+            Some(c) => {
+                let mut cmd = config_cmd("notify_cmd", c)?;
+                cmd.current_dir(&root);
+                Some(cmd)
+            }
+            None => None,
+        };
+        orc.add_reporter(Box::new(notifications::Notifier::new(
+            notify.bell,
+            notify.escape,
+            cmd,
+            logger,
+        )));
+    }
+    // Keeping the tests means reverting everything else path by path
+    if config.revert_mode == RevertMode::Changed
+        || config.failure_policy == orchestrator::FailurePolicy::KeepTests
+    {
+        orc.set_path_reverter(&mut path_reverter);
+    }
+    if config.failure_policy != orchestrator::FailurePolicy::Revert {
+        let test_paths = test_paths::TestPaths::new(root.clone(), &config.test_paths)
+            .map_err(|e| TcrError::Config(format!("test_paths: {}", e)))?;
+        orc.set_failure_policy(config.failure_policy, test_paths);
+    }
+    if let Some(path) = history_path {
+        history = history::HistoryFile::new(path, root.clone());
+        orc.set_history(&mut history);
+    }
+    if let Some(limbo) = config.limbo {
+        remote = git::GitRemote::new(root, limbo.remote, limbo.branch)?;
+        orc.set_remote(&mut remote);
+    }
+
+    return Ok(f(&mut orc));
+}
+
+// Where tcr reports what it's doing
+struct Frontend {
+    format: events::Format,
+    tracker: status::Tracker,
+    server: Option<server::Server>,
+}
+
+// What the executor hands back to us to handle in between cycles
+enum Input {
+    Key(keys::Key),
+    Control(control::Request),
+    SlowTests(slow_tests::Finished),
+}
+
+fn print_status(paused: bool, logger: &log::Logger) {
+    let mode = if paused { "paused" } else { "watching" };
+    let verbose = if logger.is_verbose() { ", verbose" } else { "" };
+    logger.info(format!("[{}{}] {}", mode, verbose, keys::HELP));
+}
+
+fn report_error(orc: &mut orchestrator::Orchestrator, err: TcrError) {
+    orc.report(events::TcrEvent::Error {
+        message: err.to_string(),
+    });
+}
+
+fn watch_and_run<P: AsRef<Path>>(
+    path: P,
+    config: Config,
+    frontend: &Frontend,
+    logger: &log::Logger,
+) -> error::Result<stats::Stats> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let root = path.as_ref().to_path_buf();
+    let cancel = executor::Cancel::default();
+    let mut executor = executor::Executor::new(rx, cancel.clone());
+
+    // Quit cleanly on Ctrl-C so the terminal is restored and stats printed
+    let quit_tx = tx.clone();
+    ctrlc::set_handler(move || {
+        let _ = quit_tx.send(executor::Event::Stop);
+    })
+    .map_err(|e| TcrError::Watch(e.to_string()))?;
+
+    let restart = if config.restart_on_change {
+        Some((checker(&root, &config, logger), cancel.clone()))
+    } else {
+        None
+    };
+    let _watcher = watcher::watch(&root, tx.clone(), restart)?;
+
+    let _raw_mode = keys::RawMode::enable()?;
+    keys::spawn_reader(tx.clone(), |key| match key {
+        keys::Key::Quit => executor::Event::Stop,
+        key => executor::Event::Other(Input::Key(key)),
+    });
+
+    let mut _control = None;
+    if path.as_ref().join(".git").is_dir() {
+        let socket = control::socket_path(path.as_ref());
+        _control = Some(control::listen(
+            &socket,
+            tx.clone(),
+            frontend.tracker.clone(),
+            |request| executor::Event::Other(Input::Control(request)),
+        )?);
+    }
+
+    let slow_test_policy = config.slow_test_policy;
+    let history = history_path(&root, &config);
+    let slow_tests = match (&config.slow_test_cmd, git_dir(&root)) {
+        (Some(cmd), Some(dir)) => Some(slow_tests::SlowTests::start(
+            &root,
+            dir.join("tcr-slow-tests"),
+            config_cmd("slow_test_cmd", cmd.clone())?,
+            tx,
+            |finished| executor::Event::Other(Input::SlowTests(finished)),
+        )),
+        (Some(_), None) => {
+            return Err(TcrError::Config(String::from(
+                "slow_test_cmd needs a git repository",
+            )))
+        }
+        (None, _) => None,
+    };
+    let cancel = Some(cancel);
+    let stats = with_orchestrator(root.clone(), config, frontend, logger, cancel, |orc| {
+        if let Some(slow_tests) = slow_tests {
+            orc.add_reporter(Box::new(slow_tests));
+        }
+        let mut paused = false;
+        print_status(paused, logger);
+        while let Some(step) = executor.step(orc) {
+            let input = match step {
+                executor::Step::Cycle(result) => {
+                    if let Err(err) = result {
+                        report_error(orc, err);
+                    }
+                    print_status(paused, logger);
+                    continue;
+                }
+                executor::Step::Other(input) => input,
+            };
+            let result = match input {
+                Input::Key(keys::Key::Pause) => {
+                    paused = !paused;
+                    executor.set_paused(paused);
+                    frontend.tracker.set_paused(paused);
+                    print_status(paused, logger);
+                    continue;
+                }
+                Input::Key(keys::Key::Verbose) => {
+                    logger.toggle_verbose();
+                    print_status(paused, logger);
+                    continue;
+                }
+                Input::Key(keys::Key::Quit) => break,
+                Input::Key(keys::Key::Run) => run_changed(orc, &root).map(Some),
+                Input::Key(keys::Key::Commit) => orc.force_commit().map(Some),
+                Input::Key(keys::Key::Undo) => {
+                    if let Err(e) = undo(&root, logger) {
+                        report_error(orc, e.into());
+                    }
+                    print_status(paused, logger);
+                    continue;
+                }
+                Input::SlowTests(finished) => {
+                    handle_slow_tests(orc, &root, slow_test_policy, &history, finished);
+                    print_status(paused, logger);
+                    continue;
+                }
+                Input::Control(request) => {
+                    let response = match request.command {
+                        control::Command::Status => {
+                            control::Response::status(&frontend.tracker.status())
+                        }
+                        control::Command::Pause | control::Command::Resume => {
+                            paused = request.command == control::Command::Pause;
+                            executor.set_paused(paused);
+                            frontend.tracker.set_paused(paused);
+                            print_status(paused, logger);
+                            control::Response::ok()
+                        }
+                        control::Command::Run => {
+                            let result = run_changed(orc, &root);
+                            print_status(paused, logger);
+                            match result {
+                                Ok(r) => control::Response::result(r),
+                                Err(e) => control::Response::error(e),
+                            }
+                        }
+                        control::Command::Undo => match undo(&root, logger) {
+                            Ok(()) => control::Response::ok(),
+                            Err(e) => control::Response::error(e),
+                        },
+                        control::Command::Shutdown => break,
+                    };
+                    let _ = request.reply.send(response);
+                    continue;
+                }
+            };
+            match result {
+                Ok(_) => {}
+                Err(err) => report_error(orc, err),
+            }
+            print_status(paused, logger);
+        }
+        return orc.stats().clone();
+    })?;
+
+    Ok(stats)
+}
+
+// Marks a commit that failed the slow tests in the history and, if asked
+// to, reverts it when nothing's been committed on top of it
+fn handle_slow_tests(
+    orc: &mut orchestrator::Orchestrator,
+    root: &Path,
+    policy: slow_tests::SlowTestPolicy,
+    history: &Option<PathBuf>,
+    finished: slow_tests::Finished,
+) {
+    let mut reverted = false;
+    if !finished.success() {
+        if let Some(path) = history {
+            if let Err(e) = history::mark_slow_tests_failed(path, &finished.commit) {
+                report_error(orc, e.into());
+            }
+        }
+        let is_head = git::head(root).ok().as_ref() == Some(&finished.commit);
+        if policy == slow_tests::SlowTestPolicy::Revert && is_head {
+            match git::revert_commit(root, &finished.commit) {
+                Ok(()) => reverted = true,
+                Err(e) => report_error(
+                    orc,
+                    TcrError::RevertFailed {
+                        reason: e.to_string(),
+                    },
+                ),
+            }
+        }
+    }
+    orc.report(finished.event(reverted));
+}
+
+// Runs a cycle over everything that's changed since the last commit
+fn run_changed(
+    orc: &mut orchestrator::Orchestrator,
+    root: &Path,
+) -> error::Result<orchestrator::CycleResult> {
+    let changed = git::changed_files(root)?;
+    return orc.run_once(changed);
+}
+
+// Sends a command to the tcr watching `root` and prints its response
+fn ctl(root: &Path, command: &str) -> io::Result<control::Response> {
+    let response = control::send(&control::socket_path(root), command)?;
+    println!("{}", serde_json::to_string(&response)?);
+    return Ok(response);
+}
+
+// Where tcr keeps its own files, out of the way of the watcher
+fn git_dir(root: &Path) -> Option<PathBuf> {
+    let dir = root.join(".git");
+    if dir.is_dir() {
+        return Some(dir);
+    }
+    return None;
+}
+
+// Defaults to inside .git
+fn history_path(root: &Path, config: &Config) -> Option<PathBuf> {
+    return match &config.history_path {
+        Some(p) => Some(root.join(p)),
+        None => git_dir(root).map(|dir| dir.join("tcr-history.jsonl")),
+    };
+}
+
+fn print_history(root: &Path, config: &Config, opts: HistoryOpts) -> io::Result<()> {
+    let path = match history_path(root, config) {
+        Some(p) => p,
+        None => return Err(io::Error::other("No history_path configured")),
+    };
+    let result = match opts.result {
+        Some(r) => Some(
+            serde_json::from_value(serde_json::Value::String(r))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        ),
+        None => None,
+    };
+    let filter = history::Filter {
+        result,
+        path: opts.path,
+        limit: opts.limit,
+    };
+    for record in filter.apply(history::read(&path)?) {
+        println!("{}", history::format_record(&record));
+    }
+    return Ok(());
+}
+
+fn undo(root: &Path, logger: &log::Logger) -> io::Result<()> {
+    let commit = git::undo_tcr_commit(root)?;
+    logger.info(format!(
+        "Undid commit {}, its changes are in the working tree",
+        commit
+    ));
+    return Ok(());
+}
+
+// Runs a single cycle over everything that's changed since the last commit
+fn run_once(
+    root: PathBuf,
+    config: Config,
+    frontend: &Frontend,
+    logger: &log::Logger,
+) -> error::Result<orchestrator::CycleResult> {
+    let changed = git::changed_files(&root)?;
+    return with_orchestrator(root, config, frontend, logger, None, |orc| {
+        orc.run_once(changed)
+    })?;
+}
+
+// Runs a cycle every time Enter is pressed, until "q" or EOF
+fn run_interactive(
+    root: PathBuf,
+    config: Config,
+    frontend: &Frontend,
+    logger: &log::Logger,
+) -> error::Result<stats::Stats> {
+    // Lines typed, or None to quit
+    let (tx, rx) = std::sync::mpsc::channel();
+    let cancel = executor::Cancel::default();
+
+    // Ctrl-C cancels whatever's running and quits, so the stats are printed
+    let quit_tx = tx.clone();
+    let quit_cancel = cancel.clone();
+    ctrlc::set_handler(move || {
+        quit_cancel.stop();
+        let _ = quit_tx.send(None);
+    })
+    .map_err(|e| io::Error::other(e.to_string()))?;
+
+    std::thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            match line {
+                Ok(line) => {
+                    if tx.send(Some(line)).is_err() {
+                        return;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        let _ = tx.send(None);
+    });
+
+    let cancel = Some(cancel);
+    return with_orchestrator(root.clone(), config, frontend, logger, cancel, |orc| {
+        logger.info("Press Enter to run tcr, q to quit");
+        while let Ok(Some(line)) = rx.recv() {
+            if line.trim() == "q" {
+                break;
+            }
+            let changed = git::changed_files(&root)?;
+            match orc.run_once(changed) {
+                Err(TcrError::Cancelled { .. }) => break,
+                Err(err) => report_error(orc, err),
+                Ok(_) => {}
+            }
+        }
+        return Ok(orc.stats().clone());
+    })?;
+}
+
+fn report_stats(stats: &stats::Stats, logger: &log::Logger, json_path: &Option<String>) {
+    let summary = stats.summary();
+    logger.info(format!("\n{}", summary));
+    if let Some(path) = json_path {
+        let json = serde_json::to_string_pretty(&summary).unwrap();
+        if let Err(e) = std::fs::write(path, json) {
+            logger.error(format!("Error writing stats to {}: {:?}", path, e));
+        }
+    }
+}
+
+// 0 when the changes were committed, 1 when they were reverted and
+// otherwise whatever suits the error
+fn exit_code(result: &error::Result<orchestrator::CycleResult>) -> i32 {
+    return match result {
+        Ok(orchestrator::CycleResult::Committed) => 0,
+        Ok(orchestrator::CycleResult::NothingToCommit) => 0,
+        // Nothing was reverted, so it isn't a 1
+        Ok(orchestrator::CycleResult::FailingTestKept) => 5,
+        Ok(_) => 1,
+        Err(e) => e.exit_code(),
+    };
+}
+
+// TODO if not specified find where the config file is, and run from there
+// --root, or wherever tcr was started, as an absolute path. The paths notify
+// reports are absolute and everything comparing them to the root, like the
+// gitignore and the path reverter, needs the root to be too.
+fn root_path(root: &Option<String>) -> io::Result<PathBuf> {
+    let root = match root {
+        Some(p) => PathBuf::from(p),
+        None => get_path()?,
+    };
+    return std::fs::canonicalize(root);
+}
+
+fn get_path() -> io::Result<std::path::PathBuf> {
+    match std::env::current_dir() {
+        Ok(p) => {
+            return Ok(p);
+        }
+        Err(e) => {
+            return Err(e);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum RevertMode {
+    // Run revert_cmd
+    All,
+    // Only restore the files that changed since the last commit
+    Changed,
+}
+
+impl Default for RevertMode {
+    fn default() -> Self {
+        return RevertMode::All;
+    }
+}
+
+fn default_remote() -> String {
+    return String::from("origin");
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct LimboConfig {
+    #[serde(default = "default_remote")]
+    remote: String,
+    // Defaults to the current branch
+    branch: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct NotificationsConfig {
+    // Ring the terminal bell after every cycle
+    #[serde(default)]
+    bell: bool,
+    // Raise a desktop notification through the terminal
+    escape: Option<notifications::Escape>,
+    // Run after every cycle with the result in TCR_* environment variables
+    notify_cmd: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Config {
+    // Either a command or a list of them to run at the same time, e.g. a
+    // linter alongside the build or several test shards
+    build_cmd: parallel::Commands,
+    test_cmd: parallel::Commands,
+    // Runs instead of test_cmd with {affected} filled in from what changed
+    test_cmd_template: Option<String>,
+    // Globs, relative to the root, to the test_cmd_template arguments that
+    // test what they match
+    #[serde(default)]
+    affected_map: std::collections::BTreeMap<String, String>,
+    // Build and test only the Cargo workspace packages the changes affect
+    #[serde(default)]
+    cargo: bool,
+    // Cancel the build or tests when a change comes in while they're running,
+    // and start again with it
+    #[serde(default)]
+    restart_on_change: bool,
+    // Runs in the background after each commit, while watching
+    slow_test_cmd: Option<String>,
+    #[serde(default)]
+    slow_test_policy: slow_tests::SlowTestPolicy,
+    revert_cmd: String,
+    commit_cmd: String,
+    // Working directory and environment for each phase's command
+    #[serde(default)]
+    build: env::PhaseConfig,
+    #[serde(default)]
+    test: env::PhaseConfig,
+    #[serde(default)]
+    commit: env::PhaseConfig,
+    #[serde(default)]
+    revert: env::PhaseConfig,
+    #[serde(default)]
+    revert_mode: RevertMode,
+    // Rebase onto and push to a remote after every commit
+    limbo: Option<LimboConfig>,
+    // Where to write a JSON Lines record of every cycle, relative to the root
+    history_path: Option<String>,
+    // Globs matching test files, relative to the root
+    #[serde(default)]
+    test_paths: Vec<String>,
+    #[serde(default)]
+    failure_policy: orchestrator::FailurePolicy,
+    // Where the test command writes its results, e.g. JUnit XML, relative to
+    // the root. Otherwise they're read from its output.
+    test_report: Option<String>,
+    #[serde(default)]
+    notifications: NotificationsConfig,
+    #[serde(default)]
+    hooks: hooks::HooksConfig,
+    // Lets web pages from this origin read --serve's responses
+    serve_allow_origin: Option<String>,
+    // Kill the build or tests if they take longer than this
+    timeout_secs: Option<u64>,
+}
+
+fn get_config(logger: &log::Logger, path: std::path::PathBuf) -> error::Result<Config> {
+    logger.debug(format!(
+        "Attempting to read from config at {}",
+        path.to_str().unwrap()
+    ));
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| TcrError::Config(format!("unable to read {}: {}", path.display(), e)))?;
+    return serde_json::from_str(&contents)
+        .map_err(|e| TcrError::Config(format!("{}: {}", path.display(), e)));
+}
+
+// Reads the config, exiting if we can't
+fn read_config(logger: &log::Logger, root: &Path, path: &Option<String>) -> Config {
+    let config = match path {
+        Some(c) => get_config(logger, std::path::PathBuf::from(c)),
+        None => get_config(logger, root.join(".tcr")),
+    };
+    match config {
+        Ok(c) => {
+            logger.debug(format!("We read the config:\n {:#?}", c));
+            return c;
+        }
+        Err(e) => {
+            logger.error(format!("error: {}", e));
+            std::process::exit(e.exit_code());
+        }
+    }
+}
+
+// Starts serving status if asked to, exiting if we can't
+fn frontend(
+    logger: &log::Logger,
+    config: &Config,
+    format: events::Format,
+    serve: &Option<String>,
+) -> Frontend {
+    let tracker = status::Tracker::default();
+    let server = match serve {
+        Some(addr) => match server::Server::start(
+            addr.as_str(),
+            tracker.clone(),
+            config.serve_allow_origin.clone(),
+        ) {
+            Ok(s) => {
+                logger.info(format!("Serving status on http://{}/status", s.addr));
+                Some(s)
+            }
+            Err(e) => {
+                logger.error(format!("error: unable to serve on {}: {}", addr, e));
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    return Frontend {
+        format,
+        tracker,
+        server,
+    };
+}
+
+// Everything the tcr binary does
+pub fn run_cli() {
+    let opts: Opts = Opts::parse();
+    let mut logger = log::Logger::from_env(opts.verbose);
+    logger.set_timestamps(opts.timestamps);
+    // Keep stdout for events
+    logger.set_all_to_stderr(opts.format == events::Format::Json);
+    if let Some(path) = &opts.log_file {
+        if let Err(e) = logger.set_file(Path::new(path)) {
+            logger.error(format!("error: unable to open log file {}: {}", path, e));
+            std::process::exit(1);
+        }
+    }
+    let root = match root_path(&opts.root) {
+        Ok(root) => root,
+        Err(e) => {
+            logger.error(format!("error: unable to find the root: {}", e));
+            std::process::exit(1);
+        }
+    };
+    logger.debug(format!("Config: {:#x?}", opts.config));
+    match opts.subcmd {
+        // Undo and ctl don't need a config
+        Some(SubCommand::Undo) => {
+            if let Err(e) = undo(&root, &logger) {
+                logger.error(format!("error: {}", e));
+                std::process::exit(1);
+            }
+        }
+        Some(SubCommand::Ctl(ctl_opts)) => match ctl(&root, &ctl_opts.command) {
+            Ok(response) if response.ok => {}
+            Ok(_) => std::process::exit(1),
+            Err(e) => {
+                logger.error(format!("error: unable to reach tcr: {}", e));
+                std::process::exit(1);
+            }
+        },
+        Some(SubCommand::History(history_opts)) => {
+            let c = read_config(&logger, &root, &opts.config);
+            if let Err(e) = print_history(&root, &c, history_opts) {
+                logger.error(format!("error: {}", e));
+                std::process::exit(1);
+            }
+        }
+        Some(SubCommand::Once) => {
+            let c = read_config(&logger, &root, &opts.config);
+            let frontend = frontend(&logger, &c, opts.format, &opts.serve);
+            let result = run_once(root, c, &frontend, &logger);
+            if let Err(e) = &result {
+                logger.error(format!("error: {}", e))
+            }
+            std::process::exit(exit_code(&result));
+        }
+        Some(SubCommand::Interactive) => {
+            let c = read_config(&logger, &root, &opts.config);
+            let frontend = frontend(&logger, &c, opts.format, &opts.serve);
+            match run_interactive(root, c, &frontend, &logger) {
+                Ok(stats) => report_stats(&stats, &logger, &opts.stats_json),
+                Err(e) => {
+                    logger.error(format!("error: {}", e));
+                    std::process::exit(e.exit_code());
+                }
+            }
+        }
+        None => {
+            let c = read_config(&logger, &root, &opts.config);
+            let frontend = frontend(&logger, &c, opts.format, &opts.serve);
+            logger.info(format!(
+                "watching {}",
+                root.to_str().expect("unable to convert path to string")
+            ));
+            match watch_and_run(root, c, &frontend, &logger) {
+                Ok(stats) => report_stats(&stats, &logger, &opts.stats_json),
+                Err(e) => {
+                    logger.error(format!("error: {}", e));
+                    std::process::exit(e.exit_code());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works() {
+        assert_eq!(2 + 2, 4);
+    }
+
+    #[test]
+    fn test_cmd_from_string() {
+        let output = cmd_from_string(String::from("ls -al")).unwrap();
+        // there might be a better way to test this
+        assert_eq!(format!("{:?}", output), "\"ls\" \"-al\"");
+
+        cmd_from_string(String::from("")).expect_err("Expected this to fail");
+    }
+
+    #[test]
+    fn relative_roots_are_made_absolute() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let root = std::fs::canonicalize(tmp_dir.path()).unwrap();
+        std::fs::write(root.join(".gitignore"), "target\n").unwrap();
+        // The tests run in the crate, so up to / from there and back down
+        let up: PathBuf = std::env::current_dir()
+            .unwrap()
+            .components()
+            .map(|_| Path::new(".."))
+            .collect();
+        let relative = up.join(root.strip_prefix("/").unwrap()).join(".");
+        let relative = Some(relative.to_string_lossy().to_string());
+        assert_eq!(root_path(&relative).unwrap(), root);
+
+        let config: Config = serde_json::from_str(
+            r#"{"build_cmd": "true", "test_cmd": "true", "commit_cmd": "true", "revert_cmd": "true"}"#,
+        )
+        .unwrap();
+        let logger = log::Logger::new(log::Level::Off);
+        let mut checker = checker(&root_path(&relative).unwrap(), &config, &logger);
+        assert!(checker.is_path_ignored(&root.join(".git").join("index"), false));
+        assert!(checker.is_path_ignored(&root.join("target").join("a"), false));
+        assert!(!checker.is_path_ignored(&root.join("a.rs"), false));
+    }
+
+    #[test]
+    fn templates_keep_the_other_commands() {
+        let cmds = |cmds: &[&str]| -> Vec<Command> {
+            return cmds
+                .iter()
+                .map(|c| cmd_from_string(c.to_string()).unwrap())
+                .collect();
+        };
+        let build = cmds(&[
+            "cargo clippy -- -D warnings",
+            "cargo +nightly build --all-features",
+            "make",
+        ]);
+        assert_eq!(
+            templates("build_cmd", &build, &None, true).unwrap(),
+            [
+                Some(String::from("cargo clippy {affected} -- -D warnings")),
+                Some(String::from(
+                    "cargo +nightly build {affected} --all-features"
+                )),
+                None
+            ]
+        );
+        assert_eq!(
+            templates("build_cmd", &build, &None, false).unwrap(),
+            [None, None, None]
+        );
+
+        let test = cmds(&["npm run lint", "jest", "jest --ci"]);
+        let configured = Some(String::from("jest {affected}"));
+        assert_eq!(
+            templates("test_cmd_template", &test, &configured, false).unwrap(),
+            [None, configured.clone(), None]
+        );
+        let pytest = Some(String::from("pytest {affected}"));
+        assert!(templates("test_cmd_template", &test, &pytest, false).is_err());
+        assert_eq!(
+            templates("test_cmd_template", &cmds(&["tox"]), &pytest, false).unwrap(),
+            [pytest]
+        );
+        let no_placeholder = Some(String::from("jest"));
+        assert!(templates("test_cmd_template", &test, &no_placeholder, false).is_err());
+    }
+
+    #[test]
+    fn test_results_are_forgotten_after_the_cycle() {
+        use events::Reporter;
+        let mut last = LastTestResults::default();
+        let tests = test_results::TestResults {
+            passed: 0,
+            failed: 1,
+            ignored: 0,
+            failures: vec![String::from("it_works")],
+        };
+        last.report(&events::TcrEvent::CycleStarted { paths: vec![] });
+        last.report(&events::TcrEvent::PhaseStarted {
+            phase: orchestrator::Phase::Test,
+        });
+        last.report(&events::TcrEvent::PhaseFinished {
+            phase: orchestrator::Phase::Test,
+            success: false,
+            exit_code: Some(101),
+            duration_ms: 0,
+            stdout: String::new(),
+            stderr: String::new(),
+            error: None,
+            tests: Some(tests.clone()),
+            diagnostics: vec![],
+        });
+        assert_eq!(*last.0.borrow(), Some(tests));
+
+        // A commit with `c` after the failing cycle mentions no tests
+        last.report(&events::TcrEvent::CycleFinished {
+            result: orchestrator::CycleResult::TestFailed,
+            paths: vec![],
+            reverted: true,
+        });
+        assert_eq!(*last.0.borrow(), None);
+    }
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(exit_code(&Ok(orchestrator::CycleResult::Committed)), 0);
+        assert_eq!(exit_code(&Ok(orchestrator::CycleResult::TestFailed)), 1);
+        assert_eq!(
+            exit_code(&Ok(orchestrator::CycleResult::FailingTestKept)),
+            5
+        );
+        assert_eq!(exit_code(&Err(io::Error::other("oops").into())), 2);
+        assert_eq!(exit_code(&Err(TcrError::Config(String::from("oops")))), 3);
+    }
+}
@@ -0,0 +1,206 @@
+use colored::*;
+use regex::Regex;
+use std::path::PathBuf;
+
+use crate::diagnostics::Diagnostic;
+use crate::events::{phase_name, Reporter, TcrEvent, TextReporter};
+use crate::log::Logger;
+use crate::orchestrator::Phase;
+use crate::test_results::TestResults;
+
+// How much of a failing phase's output we show
+const FAILURE_LINES: usize = 20;
+
+// The part of a failing command's output worth looking at: the names of
+// failing tests, then the output from the first error onwards. Without
+// either, the end of the output.
+pub fn trim_output(output: &str, tests: Option<&TestResults>, max_lines: usize) -> Vec<String> {
+    let error =
+        Regex::new(r"(?i)^\s*(error|fatal|failed|panicked)\b|\berror(\[\w+\])?:|panicked at")
+            .unwrap();
+
+    let lines: Vec<&str> = output.lines().collect();
+    let mut trimmed: Vec<String> = match tests {
+        Some(t) => t.failures.iter().map(|f| format!("FAILED {}", f)).collect(),
+        None => vec![],
+    };
+    match lines.iter().position(|l| error.is_match(l)) {
+        Some(start) => trimmed.extend(lines[start..].iter().map(|l| l.to_string())),
+        None if trimmed.is_empty() => {
+            let start = lines.len().saturating_sub(max_lines);
+            trimmed.extend(lines[start..].iter().map(|l| l.to_string()));
+        }
+        None => {}
+    }
+    trimmed.truncate(max_lines);
+    return trimmed;
+}
+
+// Like the text output, but a successful phase is a single line and only
+// the relevant part of a failure is shown. The full output of the last
+// failure goes to `failure_log`.
+pub struct CompactReporter<'a> {
+    logger: &'a Logger,
+    text: TextReporter<'a>,
+    failure_log: Option<PathBuf>,
+}
+
+impl<'a> CompactReporter<'a> {
+    pub fn new(logger: &'a Logger, failure_log: Option<PathBuf>) -> CompactReporter<'a> {
+        return CompactReporter {
+            logger,
+            text: TextReporter::new(logger),
+            failure_log,
+        };
+    }
+
+    fn report_failure(
+        &self,
+        phase: Phase,
+        reason: String,
+        output: String,
+        tests: Option<&TestResults>,
+        diagnostics: &[Diagnostic],
+    ) {
+        let summary = match tests {
+            Some(t) => format!(" ({})", t),
+            None => String::new(),
+        };
+        self.logger.warn(format!(
+            "{}: {}{}",
+            format!("{} failed", phase_name(phase)).red(),
+            reason,
+            summary
+        ));
+        let lines = if diagnostics.is_empty() {
+            trim_output(&output, tests, FAILURE_LINES)
+        } else {
+            diagnostics
+                .iter()
+                .take(FAILURE_LINES)
+                .map(|d| d.to_string())
+                .collect()
+        };
+        for line in lines {
+            self.logger.warn(format!("  {}", line));
+        }
+        if let Some(path) = &self.failure_log {
+            match std::fs::write(path, &output) {
+                Ok(()) => self
+                    .logger
+                    .info(format!("Full output in {}", path.display())),
+                Err(e) => self.logger.warn(format!(
+                    "Unable to write output to {}: {}",
+                    path.display(),
+                    e
+                )),
+            }
+        }
+    }
+}
+
+impl Reporter for CompactReporter<'_> {
+    fn report(&mut self, event: &TcrEvent) {
+        match event {
+            TcrEvent::PhaseStarted { phase } => {
+                self.logger
+                    .debug(format!("Running {}..", phase_name(*phase).to_lowercase()));
+            }
+            TcrEvent::PhaseFinished {
+                phase,
+                success: true,
+                duration_ms,
+                tests,
+                ..
+            } => {
+                let line = match tests {
+                    Some(t) => format!("{} ok ({}ms, {})", phase_name(*phase), duration_ms, t),
+                    None => format!("{} ok ({}ms)", phase_name(*phase), duration_ms),
+                };
+                match phase {
+                    Phase::Build | Phase::Test => self.logger.info(line.green().to_string()),
+                    _ => self.logger.debug(line),
+                }
+            }
+            TcrEvent::PhaseFinished {
+                phase,
+                exit_code,
+                stdout,
+                stderr,
+                error,
+                tests,
+                diagnostics,
+                ..
+            } if *phase == Phase::Build || *phase == Phase::Test => {
+                let reason = match (error, exit_code) {
+                    (Some(e), _) => e.clone(),
+                    (None, Some(code)) => format!("exited with code {}", code),
+                    (None, None) => String::from("killed by a signal"),
+                };
+                self.report_failure(
+                    *phase,
+                    reason,
+                    format!("{}{}", stdout, stderr),
+                    tests.as_ref(),
+                    diagnostics,
+                );
+            }
+            _ => self.text.report(event),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_results;
+
+    #[test]
+    fn test_trim_failing_tests() {
+        let output = "\
+running 3 tests
+test a ... ok
+test b ... FAILED
+test c ... FAILED
+
+failures:
+
+---- b stdout ----
+thread 'b' panicked at src/lib.rs:3:5:
+assertion failed
+";
+        let tests = test_results::parse(output);
+        let trimmed = trim_output(output, tests.as_ref(), 4);
+        assert_eq!(
+            trimmed,
+            [
+                "FAILED b",
+                "FAILED c",
+                "thread 'b' panicked at src/lib.rs:3:5:",
+                "assertion failed"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trim_from_first_error() {
+        let output = "\
+   Compiling tcr v0.1.0
+error[E0425]: cannot find value `x` in this scope
+ --> src/main.rs:2:5
+";
+        assert_eq!(
+            trim_output(output, None, 20),
+            [
+                "error[E0425]: cannot find value `x` in this scope",
+                " --> src/main.rs:2:5"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trim_falls_back_to_the_end() {
+        let output = "1\n2\n3\n4\n";
+        assert_eq!(trim_output(output, None, 2), ["3", "4"]);
+    }
+}
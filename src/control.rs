@@ -0,0 +1,262 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Sender};
+
+use crate::orchestrator::CycleResult;
+use crate::status::{Status, Tracker};
+
+// Commands accepted on the control socket, one per line
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Command {
+    Pause,
+    Resume,
+    Run,
+    Status,
+    Undo,
+    Shutdown,
+}
+
+impl std::str::FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Command, String> {
+        return match s.trim() {
+            "pause" => Ok(Command::Pause),
+            "resume" => Ok(Command::Resume),
+            "run" => Ok(Command::Run),
+            "status" => Ok(Command::Status),
+            "undo" => Ok(Command::Undo),
+            "shutdown" => Ok(Command::Shutdown),
+            other => Err(format!(
+                "unknown command {:?}, expected pause, resume, run, status, undo or shutdown",
+                other
+            )),
+        };
+    }
+}
+
+// Sent back as a single line of JSON
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<CycleResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<serde_json::Value>,
+}
+
+impl Response {
+    pub fn ok() -> Response {
+        return Response {
+            ok: true,
+            ..Response::default()
+        };
+    }
+
+    pub fn error(e: impl ToString) -> Response {
+        return Response {
+            ok: false,
+            error: Some(e.to_string()),
+            ..Response::default()
+        };
+    }
+
+    pub fn result(result: CycleResult) -> Response {
+        return Response {
+            result: Some(result),
+            ..Response::ok()
+        };
+    }
+
+    pub fn status(status: &Status) -> Response {
+        return Response {
+            status: Some(serde_json::to_value(status).unwrap()),
+            ..Response::ok()
+        };
+    }
+}
+
+// A command from a client, to be answered through `reply`
+pub struct Request {
+    pub command: Command,
+    pub reply: Sender<Response>,
+}
+
+// Lives inside .git so the watcher never sees it
+pub fn socket_path(root: &Path) -> PathBuf {
+    return root.join(".git").join("tcr.sock");
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::*;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    // Removes the socket when tcr exits
+    pub struct Listener {
+        path: PathBuf,
+    }
+
+    impl Drop for Listener {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    // Accepts connections on a background thread, handing each request to
+    // `f` and sending it through `tx`. Status is answered from `tracker`
+    // straight away, even in the middle of a cycle.
+    pub fn listen<T: Send + 'static>(
+        path: &Path,
+        tx: Sender<T>,
+        tracker: Tracker,
+        f: fn(Request) -> T,
+    ) -> io::Result<Listener> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("tcr is already running, {} is in use", path.display()),
+                ));
+            }
+            // Left behind by a tcr that didn't exit cleanly
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let tx = tx.clone();
+                let tracker = tracker.clone();
+                std::thread::spawn(move || {
+                    let _ = handle(stream, tx, &tracker, f);
+                });
+            }
+        });
+        return Ok(Listener {
+            path: path.to_path_buf(),
+        });
+    }
+
+    fn handle<T>(
+        stream: UnixStream,
+        tx: Sender<T>,
+        tracker: &Tracker,
+        f: fn(Request) -> T,
+    ) -> io::Result<()> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let response = match line?.parse() {
+                // Answer before tcr goes away, there's nothing to wait for
+                Ok(Command::Shutdown) => {
+                    writeln!(writer, "{}", serde_json::to_string(&Response::ok())?)?;
+                    let (reply, _rx) = channel();
+                    let _ = tx.send(f(Request {
+                        command: Command::Shutdown,
+                        reply,
+                    }));
+                    return Ok(());
+                }
+                Ok(Command::Status) => Response::status(&tracker.status()),
+                Ok(command) => {
+                    let (reply, rx) = channel();
+                    if tx.send(f(Request { command, reply })).is_err() {
+                        return Ok(());
+                    }
+                    match rx.recv() {
+                        Ok(response) => response,
+                        Err(_) => Response::error("tcr is shutting down"),
+                    }
+                }
+                Err(e) => Response::error(e),
+            };
+            writeln!(writer, "{}", serde_json::to_string(&response)?)?;
+        }
+        return Ok(());
+    }
+
+    pub fn send(path: &Path, command: &str) -> io::Result<Response> {
+        let mut stream = UnixStream::connect(path)?;
+        writeln!(stream, "{}", command)?;
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line)?;
+        return Ok(serde_json::from_str(&line)?);
+    }
+}
+
+#[cfg(unix)]
+pub use unix::{listen, send};
+
+#[cfg(not(unix))]
+pub struct Listener {}
+
+#[cfg(not(unix))]
+pub fn listen<T: Send + 'static>(
+    _path: &Path,
+    _tx: Sender<T>,
+    _tracker: Tracker,
+    _f: fn(Request) -> T,
+) -> io::Result<Listener> {
+    return Ok(Listener {});
+}
+
+#[cfg(not(unix))]
+pub fn send(_path: &Path, _command: &str) -> io::Result<Response> {
+    return Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the control socket is only available on unix",
+    ));
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!("pause".parse(), Ok(Command::Pause));
+        assert_eq!("shutdown\n".parse(), Ok(Command::Shutdown));
+        assert!("reboot".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let path = tmp_dir.path().join("tcr.sock");
+        let (tx, rx) = channel();
+        let mut tracker = Tracker::default();
+        let listener = listen(&path, tx, tracker.clone(), |r| r).unwrap();
+        std::thread::spawn(move || {
+            for request in rx {
+                let response = match request.command {
+                    Command::Run => Response::result(CycleResult::Committed),
+                    _ => Response::ok(),
+                };
+                request.reply.send(response).unwrap();
+            }
+        });
+
+        assert_eq!(
+            send(&path, "run").unwrap(),
+            Response::result(CycleResult::Committed)
+        );
+        assert!(!send(&path, "nope").unwrap().ok);
+        // Answered without waiting for tcr
+        crate::events::Reporter::report(
+            &mut tracker,
+            &crate::events::TcrEvent::PhaseStarted {
+                phase: crate::orchestrator::Phase::Build,
+            },
+        );
+        let status = send(&path, "status").unwrap().status.unwrap();
+        assert_eq!(status["state"], "running");
+        // A second tcr can't take over the socket
+        let (tx, _rx) = channel();
+        assert!(listen(&path, tx, Tracker::default(), |r: Request| r).is_err());
+
+        drop(listener);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,215 @@
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+
+// A compiler error pointing at a place in the source
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: u32,
+    pub column: Option<u32>,
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.column {
+            Some(column) => write!(
+                f,
+                "{}:{}:{}: {}",
+                self.file, self.line, column, self.message
+            ),
+            None => write!(f, "{}:{}: {}", self.file, self.line, self.message),
+        }
+    }
+}
+
+fn diagnostic(file: &str, line: &str, column: Option<&str>, message: &str) -> Diagnostic {
+    return Diagnostic {
+        file: file.to_string(),
+        line: line.parse().unwrap_or(0),
+        column: column.and_then(|c| c.parse().ok()),
+        message: message.trim().to_string(),
+    };
+}
+
+// The errors in a failed build's output, in the order they were reported.
+// Understands cargo --message-format=json, rustc, gcc, clang and tsc.
+// Warnings are left out.
+pub fn parse(output: &str) -> Vec<Diagnostic> {
+    let mut errors = parse_cargo_json(output);
+    if errors.is_empty() {
+        errors = parse_text(output);
+    }
+    // cargo reports an error once per target that includes the file
+    let mut unique = vec![];
+    for error in errors {
+        if !unique.contains(&error) {
+            unique.push(error);
+        }
+    }
+    return unique;
+}
+
+fn parse_cargo_json(output: &str) -> Vec<Diagnostic> {
+    let messages = output
+        .lines()
+        .filter(|l| l.trim_start().starts_with('{'))
+        .filter_map(|l| serde_json::from_str::<Value>(l).ok())
+        .filter(|v| v["reason"] == "compiler-message");
+
+    let mut errors = vec![];
+    for message in messages {
+        let message = &message["message"];
+        if message["level"] != "error" {
+            continue;
+        }
+        let spans = message["spans"].as_array().cloned().unwrap_or_default();
+        // Errors without a place, like "aborting due to 2 previous errors",
+        // aren't worth listing
+        let span = match spans.iter().find(|s| s["is_primary"] == true) {
+            Some(span) => span,
+            None => continue,
+        };
+        errors.push(Diagnostic {
+            file: span["file_name"].as_str().unwrap_or("").to_string(),
+            line: span["line_start"].as_u64().unwrap_or(0) as u32,
+            column: span["column_start"].as_u64().map(|c| c as u32),
+            message: message["message"].as_str().unwrap_or("").to_string(),
+        });
+    }
+    return errors;
+}
+
+fn parse_text(output: &str) -> Vec<Diagnostic> {
+    // rustc puts the place on the line after the message
+    let rustc = Regex::new(r"^error(?:\[\w+\])?: (.*)$").unwrap();
+    let rustc_place = Regex::new(r"^\s*--> (.+?):(\d+):(\d+)$").unwrap();
+    let gcc = Regex::new(r"^(.+?):(\d+):(?:(\d+):)? (?:fatal )?error: (.*)$").unwrap();
+    let tsc = Regex::new(r"^(.+?)\((\d+),(\d+)\): error (TS\d+: .*)$").unwrap();
+    let tsc_pretty = Regex::new(r"^(.+?):(\d+):(\d+) - error (TS\d+: .*)$").unwrap();
+
+    let lines: Vec<&str> = output.lines().collect();
+    let mut errors = vec![];
+    for (i, line) in lines.iter().enumerate() {
+        if let Some(c) = rustc.captures(line) {
+            if let Some(place) = lines.get(i + 1).and_then(|l| rustc_place.captures(l)) {
+                errors.push(diagnostic(&place[1], &place[2], Some(&place[3]), &c[1]));
+            }
+        } else if let Some(c) = gcc.captures(line) {
+            let column = c.get(3).map(|m| m.as_str());
+            errors.push(diagnostic(&c[1], &c[2], column, &c[4]));
+        } else if let Some(c) = tsc.captures(line).or_else(|| tsc_pretty.captures(line)) {
+            errors.push(diagnostic(&c[1], &c[2], Some(&c[3]), &c[4]));
+        }
+    }
+    return errors;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(file: &str, line: u32, column: Option<u32>, message: &str) -> Diagnostic {
+        return Diagnostic {
+            file: file.to_string(),
+            line,
+            column,
+            message: message.to_string(),
+        };
+    }
+
+    #[test]
+    fn test_rustc() {
+        let output = "\
+   Compiling tcr v0.1.0
+warning: unused variable: `y`
+ --> src/lib.rs:1:5
+error[E0425]: cannot find value `x` in this scope
+ --> src/main.rs:2:5
+  |
+2 |     x
+  |     ^ not found in this scope
+
+error: aborting due to previous error
+";
+        let errors = parse(output);
+        assert_eq!(
+            errors,
+            [error(
+                "src/main.rs",
+                2,
+                Some(5),
+                "cannot find value `x` in this scope"
+            )]
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            "src/main.rs:2:5: cannot find value `x` in this scope"
+        );
+    }
+
+    #[test]
+    fn test_cargo_json() {
+        let output = r#"{"reason":"compiler-artifact","target":{"name":"a"}}
+{"reason":"compiler-message","message":{"level":"warning","message":"unused","spans":[{"file_name":"src/a.rs","line_start":1,"column_start":1,"is_primary":true}]}}
+{"reason":"compiler-message","message":{"level":"error","message":"mismatched types","spans":[{"file_name":"src/b.rs","line_start":7,"column_start":9,"is_primary":true}]}}
+{"reason":"compiler-message","message":{"level":"error","message":"mismatched types","spans":[{"file_name":"src/b.rs","line_start":7,"column_start":9,"is_primary":true}]}}
+{"reason":"compiler-message","message":{"level":"error","message":"aborting due to previous error","spans":[]}}
+"#;
+        assert_eq!(
+            parse(output),
+            [error("src/b.rs", 7, Some(9), "mismatched types")]
+        );
+    }
+
+    #[test]
+    fn test_gcc_and_clang() {
+        let output = "\
+main.c: In function 'main':
+main.c:3:5: error: 'x' undeclared (first use in this function)
+main.c:4:1: warning: control reaches end of non-void function
+util.h:10: error: expected ';' before '}' token
+main.c:1:10: fatal error: 'missing.h' file not found
+";
+        assert_eq!(
+            parse(output),
+            [
+                error(
+                    "main.c",
+                    3,
+                    Some(5),
+                    "'x' undeclared (first use in this function)"
+                ),
+                error("util.h", 10, None, "expected ';' before '}' token"),
+                error("main.c", 1, Some(10), "'missing.h' file not found"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tsc() {
+        let output = "\
+src/app.ts(12,7): error TS2322: Type 'string' is not assignable to type 'number'.
+src/index.ts:3:1 - error TS2304: Cannot find name 'foo'.
+";
+        assert_eq!(
+            parse(output),
+            [
+                error(
+                    "src/app.ts",
+                    12,
+                    Some(7),
+                    "TS2322: Type 'string' is not assignable to type 'number'."
+                ),
+                error(
+                    "src/index.ts",
+                    3,
+                    Some(1),
+                    "TS2304: Cannot find name 'foo'."
+                ),
+            ]
+        );
+        assert_eq!(parse("make: *** [all] Error 1"), []);
+    }
+}
@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+
+use crate::events::{Reporter, TcrEvent};
+use crate::orchestrator::Phase;
+
+// Where and with what environment a phase's command runs
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct PhaseConfig {
+    // Relative to the root. Defaults to wherever tcr was started.
+    cwd: Option<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    // A .env file, relative to the root. `env` takes precedence over it.
+    env_file: Option<String>,
+}
+
+// KEY=value lines. Blank lines and comments are skipped, `export` is
+// allowed and values may be quoted.
+pub fn parse_env_file(contents: &str) -> Result<Vec<(String, String)>, String> {
+    let mut vars = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => (key.trim(), value.trim()),
+            _ => return Err(format!("line {}: expected KEY=value", i + 1)),
+        };
+        let value = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+            value[1..value.len() - 1]
+                .replace("\\n", "\n")
+                .replace("\\\"", "\"")
+        } else if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+            value[1..value.len() - 1].to_string()
+        } else {
+            // Unquoted values can have a comment after them
+            match value.find(" #") {
+                Some(end) => value[..end].trim_end().to_string(),
+                None => value.to_string(),
+            }
+        };
+        vars.push((key.to_string(), value));
+    }
+    return Ok(vars);
+}
+
+// A new command with `template`'s program, arguments, directory and
+// environment, for setting up one run without touching the next
+pub fn copy(template: &Command) -> Command {
+    let mut cmd = Command::new(template.get_program());
+    cmd.args(template.get_args());
+    if let Some(dir) = template.get_current_dir() {
+        cmd.current_dir(dir);
+    }
+    for (key, value) in template.get_envs() {
+        match value {
+            Some(value) => cmd.env(key, value),
+            None => cmd.env_remove(key),
+        };
+    }
+    return cmd;
+}
+
+// The paths in the current cycle, kept up to date by reporting events to it
+#[derive(Clone, Default)]
+pub struct ChangedFiles(Rc<RefCell<Vec<PathBuf>>>);
+
+impl ChangedFiles {
+    pub fn paths(&self) -> Vec<PathBuf> {
+        return self.0.borrow().clone();
+    }
+}
+
+impl Reporter for ChangedFiles {
+    fn report(&mut self, event: &TcrEvent) {
+        if let TcrEvent::CycleStarted { paths } = event {
+            *self.0.borrow_mut() = paths.clone();
+        }
+    }
+}
+
+// Sets up a phase's command before every run
+pub struct PhaseEnv {
+    root: PathBuf,
+    phase: Phase,
+    config: PhaseConfig,
+    changed: ChangedFiles,
+}
+
+impl PhaseEnv {
+    pub fn new(root: &Path, phase: Phase, config: PhaseConfig, changed: ChangedFiles) -> PhaseEnv {
+        return PhaseEnv {
+            root: root.to_path_buf(),
+            phase,
+            config,
+            changed,
+        };
+    }
+
+    fn vars(&self) -> io::Result<Vec<(String, String)>> {
+        let paths: Vec<String> = self
+            .changed
+            .paths()
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+        let phase = serde_json::to_value(self.phase).unwrap();
+        let mut vars = vec![
+            (
+                String::from("TCR_ROOT"),
+                self.root.to_string_lossy().to_string(),
+            ),
+            (
+                String::from("TCR_PHASE"),
+                phase.as_str().unwrap().to_string(),
+            ),
+            (String::from("TCR_CHANGED_FILES"), paths.join("\n")),
+        ];
+        // Read every time, so editing it doesn't need a restart
+        if let Some(file) = &self.config.env_file {
+            let path = self.root.join(file);
+            let contents = std::fs::read_to_string(&path).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("unable to read {}: {}", path.display(), e),
+                )
+            })?;
+            let file_vars = parse_env_file(&contents).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), e),
+                )
+            })?;
+            vars.extend(file_vars);
+        }
+        vars.extend(self.config.env.clone());
+        return Ok(vars);
+    }
+
+    // Fails when env_file is missing or malformed, so that can be reported
+    // as a bad config up front rather than when a phase runs
+    pub fn check(&self) -> io::Result<()> {
+        self.vars()?;
+        return Ok(());
+    }
+
+    // A copy of `template` set up for this run. A fresh one each time, so
+    // variables taken out of env_file don't linger from an earlier run.
+    pub fn command(&self, template: &Command) -> io::Result<Command> {
+        let mut cmd = copy(template);
+        if let Some(cwd) = &self.config.cwd {
+            cmd.current_dir(self.root.join(cwd));
+        }
+        cmd.envs(self.vars()?);
+        return Ok(cmd);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_env_file() {
+        let contents = r#"
+# Comment
+A=1
+export B = two
+C="quoted # not a comment\nnext"
+D='single'
+E=plain # comment
+"#;
+        assert_eq!(
+            parse_env_file(contents).unwrap(),
+            [
+                (String::from("A"), String::from("1")),
+                (String::from("B"), String::from("two")),
+                (
+                    String::from("C"),
+                    String::from("quoted # not a comment\nnext")
+                ),
+                (String::from("D"), String::from("single")),
+                (String::from("E"), String::from("plain")),
+            ]
+        );
+        assert_eq!(
+            parse_env_file("A=1\noops"),
+            Err(String::from("line 2: expected KEY=value"))
+        );
+    }
+
+    #[test]
+    fn commands_get_the_phase_environment() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let root = tmp_dir.path();
+        std::fs::create_dir(root.join("sub")).unwrap();
+        std::fs::write(root.join(".env"), "FROM_FILE=file\nOVERRIDDEN=file\n").unwrap();
+        let config: PhaseConfig = serde_json::from_str(
+            r#"{"cwd": "sub", "env": {"OVERRIDDEN": "map"}, "env_file": ".env"}"#,
+        )
+        .unwrap();
+        let mut changed = ChangedFiles::default();
+        changed.report(&TcrEvent::CycleStarted {
+            paths: vec![PathBuf::from("a.rs"), PathBuf::from("b.rs")],
+        });
+
+        let env = PhaseEnv::new(root, Phase::Test, config, changed);
+        let mut template = Command::new("sh");
+        template.args([
+            "-c",
+            "echo \"$(basename $PWD) $TCR_PHASE $FROM_FILE $OVERRIDDEN\"; echo \"$TCR_CHANGED_FILES\"",
+        ]);
+        let output = env.command(&template).unwrap().output().unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "sub test file map\na.rs\nb.rs\n"
+        );
+
+        // Taking a variable out of the file takes it out of the next run
+        std::fs::write(root.join(".env"), "OVERRIDDEN=file\n").unwrap();
+        let output = env.command(&template).unwrap().output().unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "sub test  map\na.rs\nb.rs\n"
+        );
+    }
+
+    #[test]
+    fn missing_or_bad_env_files_fail_the_check() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let root = tmp_dir.path();
+        let config: PhaseConfig = serde_json::from_str(r#"{"env_file": ".env"}"#).unwrap();
+        let env = PhaseEnv::new(root, Phase::Build, config, ChangedFiles::default());
+        assert!(env.check().is_err());
+
+        std::fs::write(root.join(".env"), "oops\n").unwrap();
+        assert!(env.check().is_err());
+
+        std::fs::write(root.join(".env"), "A=1\n").unwrap();
+        assert!(env.check().is_ok());
+    }
+}
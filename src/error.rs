@@ -0,0 +1,158 @@
+use std::io;
+
+use crate::events::phase_name;
+use crate::orchestrator::Phase;
+
+// Everything that can stop tcr doing what it was asked to
+#[derive(Debug)]
+pub enum TcrError {
+    // The phase's command couldn't be started
+    SpawnFailed { phase: Phase, source: io::Error },
+    NonZeroExit { phase: Phase, code: i32 },
+    // None if we can't tell which signal it was
+    Signaled { phase: Phase, signal: Option<i32> },
+    Timeout { phase: Phase },
+    // Stopped part way through, e.g. because newer changes came in
+    Cancelled { phase: Phase },
+    CommitFailed { reason: String },
+    RevertFailed { reason: String },
+    Config(String),
+    Watch(String),
+    // Anything else, e.g. talking to git or reading the history
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, TcrError>;
+
+impl TcrError {
+    // Works out how the command for `phase` went wrong, if it did
+    pub fn from_output(
+        phase: Phase,
+        output: &io::Result<std::process::Output>,
+    ) -> Option<TcrError> {
+        return match output {
+            Ok(out) if out.status.success() => None,
+            Ok(out) => match out.status.code() {
+                Some(code) => Some(TcrError::NonZeroExit { phase, code }),
+                None => Some(TcrError::Signaled {
+                    phase,
+                    signal: signal(&out.status),
+                }),
+            },
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => Some(TcrError::Timeout { phase }),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => Some(TcrError::Cancelled { phase }),
+            Err(e) => Some(TcrError::SpawnFailed {
+                phase,
+                source: io::Error::new(e.kind(), e.to_string()),
+            }),
+        };
+    }
+
+    // 2 for most errors, so scripts can tell them apart from a revert (1).
+    // Signals and timeouts follow the shell's conventions.
+    pub fn exit_code(&self) -> i32 {
+        return match self {
+            TcrError::Signaled {
+                signal: Some(signal),
+                ..
+            } => 128 + signal,
+            TcrError::Timeout { .. } => 124,
+            TcrError::Config(_) => 3,
+            TcrError::Watch(_) => 4,
+            _ => 2,
+        };
+    }
+}
+
+#[cfg(unix)]
+fn signal(status: &std::process::ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    return status.signal();
+}
+
+#[cfg(not(unix))]
+fn signal(_status: &std::process::ExitStatus) -> Option<i32> {
+    return None;
+}
+
+impl std::fmt::Display for TcrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = |phase: &Phase| phase_name(*phase).to_lowercase();
+        match self {
+            TcrError::SpawnFailed { phase, source } => {
+                write!(f, "unable to run the {} command: {}", name(phase), source)
+            }
+            TcrError::NonZeroExit { phase, code } => {
+                write!(f, "{} command exited with code {}", name(phase), code)
+            }
+            TcrError::Signaled {
+                phase,
+                signal: Some(signal),
+            } => write!(f, "{} command killed by signal {}", name(phase), signal),
+            TcrError::Signaled {
+                phase,
+                signal: None,
+            } => write!(f, "{} command killed by a signal", name(phase)),
+            TcrError::Timeout { phase } => write!(f, "{} command timed out", name(phase)),
+            TcrError::Cancelled { phase } => write!(f, "{} command cancelled", name(phase)),
+            TcrError::CommitFailed { reason } => write!(f, "unable to commit: {}", reason),
+            TcrError::RevertFailed { reason } => write!(f, "unable to revert: {}", reason),
+            TcrError::Config(message) => write!(f, "bad config: {}", message),
+            TcrError::Watch(message) => write!(f, "unable to watch for changes: {}", message),
+            TcrError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for TcrError {}
+
+impl From<io::Error> for TcrError {
+    fn from(e: io::Error) -> TcrError {
+        return TcrError::Io(e);
+    }
+}
+
+impl From<notify::Error> for TcrError {
+    fn from(e: notify::Error) -> TcrError {
+        return TcrError::Watch(e.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn test_from_output() {
+        let ok = Command::new("true").output();
+        assert!(TcrError::from_output(Phase::Build, &ok).is_none());
+
+        let failed = Command::new("sh").args(["-c", "exit 3"]).output();
+        let e = TcrError::from_output(Phase::Test, &failed).unwrap();
+        assert_eq!(e.to_string(), "test command exited with code 3");
+        assert_eq!(e.exit_code(), 2);
+
+        let missing = Command::new("not-a-real-command").output();
+        let e = TcrError::from_output(Phase::Build, &missing).unwrap();
+        assert!(e
+            .to_string()
+            .starts_with("unable to run the build command: "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_signaled() {
+        let killed = Command::new("sh").args(["-c", "kill -9 $$"]).output();
+        let e = TcrError::from_output(Phase::Test, &killed).unwrap();
+        assert_eq!(e.to_string(), "test command killed by signal 9");
+        assert_eq!(e.exit_code(), 137);
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(TcrError::Timeout { phase: Phase::Test }.exit_code(), 124);
+        assert_eq!(TcrError::Config(String::from("oops")).exit_code(), 3);
+        assert_eq!(TcrError::Watch(String::from("oops")).exit_code(), 4);
+    }
+}
@@ -0,0 +1,388 @@
+use chrono::{DateTime, Utc};
+use colored::*;
+use itertools::Itertools;
+use serde::Serialize;
+use std::path::PathBuf;
+
+use crate::compact::CompactReporter;
+use crate::diagnostics::Diagnostic;
+use crate::log::{Level, Logger};
+use crate::orchestrator::{CycleResult, Phase};
+use crate::test_results::TestResults;
+
+// Everything the orchestrator has to say about what it's doing
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TcrEvent {
+    ChangeSeen {
+        paths: Vec<PathBuf>,
+    },
+    Ignored {
+        paths: Vec<PathBuf>,
+    },
+    Debounced {
+        paths: Vec<PathBuf>,
+    },
+    CycleStarted {
+        paths: Vec<PathBuf>,
+    },
+    PhaseStarted {
+        phase: Phase,
+    },
+    PhaseFinished {
+        phase: Phase,
+        success: bool,
+        // None if the command couldn't be run or was killed by a signal
+        exit_code: Option<i32>,
+        duration_ms: u64,
+        stdout: String,
+        stderr: String,
+        // Set if the command couldn't be run at all
+        error: Option<String>,
+        // For the test phase, if we could make sense of its output
+        tests: Option<TestResults>,
+        // For a failed build, the compiler errors we found in its output
+        diagnostics: Vec<Diagnostic>,
+    },
+    Committed,
+    // The commit command found nothing to commit
+    NothingToCommit,
+    Reverting {
+        paths: Vec<PathBuf>,
+    },
+    Reverted {
+        paths: Vec<PathBuf>,
+    },
+    // Relaxed policy: the tests failed but only tests changed
+    FailingTestKept {
+        paths: Vec<PathBuf>,
+    },
+    // Keep tests policy: everything but these was reverted
+    TestsKept {
+        paths: Vec<PathBuf>,
+    },
+    Rebasing,
+    Rebased,
+    RebaseConflict,
+    Pushed,
+    PushFailed {
+        error: String,
+    },
+    ResetToUpstream,
+    CycleFinished {
+        result: CycleResult,
+        // Everything that changed since the last commit
+        paths: Vec<PathBuf>,
+        reverted: bool,
+    },
+    // The cycle was stopped part way through `phase`, before anything was
+    // committed or reverted
+    Cancelled {
+        phase: Phase,
+    },
+    Error {
+        message: String,
+    },
+    // slow_test_cmd finished testing `commit` in the background
+    SlowTestsFinished {
+        commit: String,
+        success: bool,
+        exit_code: Option<i32>,
+        duration_ms: u64,
+        stdout: String,
+        stderr: String,
+        error: Option<String>,
+        tests: Option<TestResults>,
+        // The commit was reverted because of it
+        reverted: bool,
+    },
+}
+
+pub trait Reporter {
+    fn report(&mut self, event: &TcrEvent);
+}
+
+fn join(paths: &[PathBuf]) -> String {
+    return paths.iter().map(|p| p.to_string_lossy()).join(", ");
+}
+
+pub fn phase_name(phase: Phase) -> &'static str {
+    return match phase {
+        Phase::Build => "Build",
+        Phase::Test => "Test",
+        Phase::Commit => "Commit",
+        Phase::Revert => "Revert",
+    };
+}
+
+// The default human readable, coloured output
+pub struct TextReporter<'a> {
+    logger: &'a Logger,
+}
+
+impl<'a> TextReporter<'a> {
+    pub fn new(logger: &'a Logger) -> TextReporter<'a> {
+        return TextReporter { logger };
+    }
+}
+
+impl Reporter for TextReporter<'_> {
+    fn report(&mut self, event: &TcrEvent) {
+        let logger = self.logger;
+        match event {
+            TcrEvent::ChangeSeen { paths } => {
+                logger.info(format!("{}: {}", "Saw file changes".yellow(), join(paths)))
+            }
+            TcrEvent::Ignored { paths } => logger.debug(format!(
+                "{} {}",
+                "Files are ignored: ".yellow(),
+                join(paths)
+            )),
+            TcrEvent::Debounced { .. } => logger.debug(
+                "Debouncing. Change occurred to close to last change"
+                    .yellow()
+                    .to_string(),
+            ),
+            TcrEvent::PhaseStarted { phase } => match phase {
+                Phase::Build => logger.info("Running build.."),
+                Phase::Test => logger.info("Running tests.."),
+                _ => logger.debug(format!("Running {}..", phase_name(*phase).to_lowercase())),
+            },
+            TcrEvent::PhaseFinished {
+                phase,
+                success,
+                exit_code,
+                stdout,
+                stderr,
+                error,
+                tests,
+                diagnostics,
+                ..
+            } => {
+                if *phase == Phase::Build || *phase == Phase::Test {
+                    // The errors are listed below, so the whole output is
+                    // only needed when you ask for it
+                    let level = if diagnostics.is_empty() {
+                        Level::Info
+                    } else {
+                        Level::Debug
+                    };
+                    for out in &[stdout, stderr] {
+                        if !out.is_empty() {
+                            logger.log(level, out);
+                        }
+                    }
+                }
+                match (phase, success) {
+                    (Phase::Build, true) => logger.info("Build succeeded".green().to_string()),
+                    (Phase::Test, true) => match tests {
+                        Some(t) => logger.info(format!("{} ({})", "Tests passed".green(), t)),
+                        None => logger.info("Tests passed".green().to_string()),
+                    },
+                    // Commit and revert failures are reported as errors
+                    (Phase::Commit, _) | (Phase::Revert, _) => {}
+                    (_, false) => {
+                        let reason = match (error, exit_code) {
+                            (Some(e), _) => e.clone(),
+                            (None, Some(code)) => format!("exited with code {}", code),
+                            (None, None) => String::from("killed by a signal"),
+                        };
+                        logger.warn(format!(
+                            "{}: {}",
+                            format!("{} failed", phase_name(*phase)).red(),
+                            reason
+                        ));
+                        if let Some(t) = tests {
+                            logger.warn(t.to_string());
+                            for failure in &t.failures {
+                                logger.warn(format!("  FAILED {}", failure));
+                            }
+                        }
+                        for diagnostic in diagnostics {
+                            logger.warn(format!("  {}", diagnostic));
+                        }
+                    }
+                }
+            }
+            TcrEvent::Committed => logger.info("Changes committed".green().to_string()),
+            TcrEvent::NothingToCommit => logger.info("Nothing to commit"),
+            TcrEvent::CycleStarted { .. } => {}
+            TcrEvent::Reverting { paths } => {
+                logger.debug(format!("Reverting {} changed path(s)..", paths.len()))
+            }
+            TcrEvent::Reverted { paths } => {
+                logger.debug(format!("Reverted {} changed path(s)", paths.len()))
+            }
+            TcrEvent::FailingTestKept { .. } => logger.info(
+                "Only tests changed, keeping the failing test"
+                    .yellow()
+                    .to_string(),
+            ),
+            TcrEvent::TestsKept { paths } => logger.info(
+                format!("Keeping {} changed test file(s)", paths.len())
+                    .yellow()
+                    .to_string(),
+            ),
+            TcrEvent::Rebasing => logger.info("Rebasing onto upstream.."),
+            TcrEvent::Rebased => logger.info("Rebased, running tests again.."),
+            TcrEvent::RebaseConflict => {
+                logger.warn("Conflict rebasing onto upstream".red().to_string())
+            }
+            TcrEvent::Pushed => logger.info("Changes pushed".green().to_string()),
+            TcrEvent::PushFailed { error } => {
+                logger.warn(format!("{}: {}", "Push failed".red(), error))
+            }
+            TcrEvent::ResetToUpstream => logger.info("Reverted to upstream".yellow().to_string()),
+            TcrEvent::CycleFinished { .. } => {}
+            TcrEvent::Cancelled { phase } => logger.info(
+                format!("{} cancelled", phase_name(*phase))
+                    .yellow()
+                    .to_string(),
+            ),
+            TcrEvent::Error { message } => logger.error(format!("Error: {}", message)),
+            TcrEvent::SlowTestsFinished {
+                commit,
+                success,
+                exit_code,
+                stdout,
+                stderr,
+                error,
+                tests,
+                reverted,
+                ..
+            } => {
+                let commit: String = commit.chars().take(7).collect();
+                let summary = match tests {
+                    Some(t) => format!(" ({})", t),
+                    None => String::new(),
+                };
+                if *success {
+                    logger.info(format!(
+                        "{} on {}{}",
+                        "Slow tests passed".green(),
+                        commit,
+                        summary
+                    ));
+                    return;
+                }
+                for out in &[stdout, stderr] {
+                    if !out.is_empty() {
+                        logger.debug(out);
+                    }
+                }
+                let reason = match (error, exit_code) {
+                    (Some(e), _) => e.clone(),
+                    (None, Some(code)) => format!("exited with code {}", code),
+                    (None, None) => String::from("killed by a signal"),
+                };
+                logger.warn(format!(
+                    "{} on {}: {}{}",
+                    "Slow tests failed".red(),
+                    commit,
+                    reason,
+                    summary
+                ));
+                if let Some(t) = tests {
+                    for failure in &t.failures {
+                        logger.warn(format!("  FAILED {}", failure));
+                    }
+                }
+                if *reverted {
+                    logger.warn(format!("Reverted {}", commit).yellow().to_string());
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    // Text, but one line per successful phase and trimmed failures
+    Compact,
+    Json,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        return match s {
+            "text" => Ok(Format::Text),
+            "compact" => Ok(Format::Compact),
+            "json" => Ok(Format::Json),
+            _ => Err(format!(
+                "unknown format {:?}, expected text, compact or json",
+                s
+            )),
+        };
+    }
+}
+
+impl Format {
+    // `failure_log` is where compact output saves the full output of the
+    // last failing phase
+    pub fn reporter<'a>(
+        self,
+        logger: &'a Logger,
+        failure_log: Option<PathBuf>,
+    ) -> Box<dyn Reporter + 'a> {
+        return match self {
+            Format::Text => Box::new(TextReporter::new(logger)),
+            Format::Compact => Box::new(CompactReporter::new(logger, failure_log)),
+            Format::Json => Box::new(JsonReporter {}),
+        };
+    }
+}
+
+#[derive(Serialize)]
+struct TimedEvent<'a> {
+    time: DateTime<Utc>,
+    #[serde(flatten)]
+    event: &'a TcrEvent,
+}
+
+// The event as a single line of JSON, stamped with the current time
+pub fn to_json(event: &TcrEvent) -> String {
+    let timed = TimedEvent {
+        time: Utc::now(),
+        event,
+    };
+    return serde_json::to_string(&timed).unwrap();
+}
+
+// One JSON object per line, for editor plugins and dashboards
+pub struct JsonReporter {}
+
+impl Reporter for JsonReporter {
+    fn report(&mut self, event: &TcrEvent) {
+        println!("{}", to_json(event));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_shape() {
+        let event = TcrEvent::PhaseStarted {
+            phase: Phase::Build,
+        };
+        let timed = TimedEvent {
+            time: Utc::now(),
+            event: &event,
+        };
+        let value = serde_json::to_value(&timed).unwrap();
+        assert_eq!(value["event"], "phase_started");
+        assert_eq!(value["phase"], "build");
+        assert!(value["time"].is_string());
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!("json".parse(), Ok(Format::Json));
+        assert_eq!("text".parse(), Ok(Format::Text));
+        assert!("xml".parse::<Format>().is_err());
+    }
+}
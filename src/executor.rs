@@ -0,0 +1,255 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Condvar, Mutex};
+
+use crate::error::{Result, TcrError};
+use crate::events::TcrEvent;
+use crate::orchestrator::{CycleResult, FileChangeEvent, Orchestrator};
+
+// While the build and tests run
+const ARMED: u8 = 1;
+const CANCELLED: u8 = 2;
+// Every armed run from now on is cancelled
+const STOPPED: u8 = 4;
+
+// Lets another thread stop the build or tests while they're running. Only
+// runs in between arm and disarm can be cancelled, so committing, reverting
+// and syncing always finish.
+#[derive(Clone, Default)]
+pub struct Cancel(Arc<AtomicU8>);
+
+impl Cancel {
+    // Called when the build starts, so cancelling in between cycles does
+    // nothing
+    pub fn arm(&self) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |s| {
+                Some(ARMED | (s & STOPPED))
+            });
+    }
+
+    pub fn disarm(&self) {
+        self.0.fetch_and(STOPPED, Ordering::SeqCst);
+    }
+
+    // Returns whether there was anything running to cancel
+    pub fn cancel(&self) -> bool {
+        return self
+            .0
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |s| {
+                if s & ARMED != 0 {
+                    Some(s | CANCELLED)
+                } else {
+                    None
+                }
+            })
+            .is_ok();
+    }
+
+    pub fn stop(&self) {
+        self.0.fetch_or(STOPPED, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        let state = self.0.load(Ordering::SeqCst);
+        return state & ARMED != 0 && state & (CANCELLED | STOPPED) != 0;
+    }
+}
+
+// What the executor can be asked to do. `T` is for anything else the
+// frontend wants handled in between cycles, e.g. key presses.
+pub enum Event<T> {
+    Change(Vec<FileChangeEvent>),
+    WatchFailed(String),
+    // Run a cycle as if these paths had just changed
+    Run(Vec<PathBuf>),
+    // Commit without building or testing
+    Commit,
+    // Cancels whatever's running and stops
+    Stop,
+    Other(T),
+}
+
+struct Pending<T> {
+    events: VecDeque<Event<T>>,
+    // Everything sending events has gone
+    closed: bool,
+}
+
+// Events waiting for the executor. Changes that arrive together, e.g. while
+// a cycle is running, are merged so one cycle handles them all.
+struct Queue<T> {
+    pending: Mutex<Pending<T>>,
+    ready: Condvar,
+}
+
+impl<T> Queue<T> {
+    fn new() -> Queue<T> {
+        return Queue {
+            pending: Mutex::new(Pending {
+                events: VecDeque::new(),
+                closed: false,
+            }),
+            ready: Condvar::new(),
+        };
+    }
+
+    fn push(&self, event: Event<T>) {
+        let mut pending = self.pending.lock().unwrap();
+        match (event, pending.events.back_mut()) {
+            (Event::Change(events), Some(Event::Change(queued))) => queued.extend(events),
+            // Don't start anything else first
+            (Event::Stop, _) => pending.events.push_front(Event::Stop),
+            (event, _) => pending.events.push_back(event),
+        }
+        self.ready.notify_one();
+    }
+
+    fn close(&self) {
+        self.pending.lock().unwrap().closed = true;
+        self.ready.notify_one();
+    }
+
+    // None once it's closed and empty
+    fn pop(&self) -> Option<Event<T>> {
+        let mut pending = self.pending.lock().unwrap();
+        loop {
+            if let Some(event) = pending.events.pop_front() {
+                return Some(event);
+            }
+            if pending.closed {
+                return None;
+            }
+            pending = self.ready.wait(pending).unwrap();
+        }
+    }
+}
+
+// What handling an event came to
+pub enum Step<T> {
+    Cycle(Result<CycleResult>),
+    Other(T),
+}
+
+// Drives an orchestrator with events from a channel. A queue task keeps
+// reading the channel while a cycle runs, so nothing backs up, and a Stop
+// cancels the build or tests rather than waiting for them.
+pub struct Executor<T> {
+    queue: Arc<Queue<T>>,
+    paused: bool,
+}
+
+impl<T: Send + 'static> Executor<T> {
+    // `cancel` should be the one the orchestrator arms for the build and
+    // tests
+    pub fn new(rx: Receiver<Event<T>>, cancel: Cancel) -> Executor<T> {
+        let queue = Arc::new(Queue::new());
+        let sender_queue = queue.clone();
+        std::thread::spawn(move || {
+            for event in rx {
+                if let Event::Stop = event {
+                    cancel.stop();
+                }
+                sender_queue.push(event);
+            }
+            sender_queue.close();
+        });
+        return Executor {
+            queue,
+            paused: false,
+        };
+    }
+}
+
+impl<T> Executor<T> {
+    // Changes don't start a cycle while paused, though they're still
+    // remembered for the next revert
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    // Waits for the next event and handles it, returning None once stopped.
+    // Changes that are ignored or debounced and cycles that were cancelled
+    // aren't returned: the executor just moves on to the next event.
+    pub fn step(&mut self, orc: &mut Orchestrator) -> Option<Step<T>> {
+        loop {
+            let result = match self.queue.pop()? {
+                Event::Change(events) if self.paused => {
+                    orc.record_events(&events);
+                    continue;
+                }
+                Event::Change(events) => orc.handle_events(events),
+                Event::WatchFailed(message) => {
+                    orc.report(TcrEvent::Error {
+                        message: format!("watch error: {}", message),
+                    });
+                    continue;
+                }
+                Event::Run(paths) => orc.run_once(paths).map(Some),
+                Event::Commit => orc.force_commit().map(Some),
+                Event::Stop => return None,
+                Event::Other(other) => return Some(Step::Other(other)),
+            };
+            match result {
+                Ok(None) | Err(TcrError::Cancelled { .. }) => continue,
+                Ok(Some(r)) => return Some(Step::Cycle(Ok(r))),
+                Err(e) => return Some(Step::Cycle(Err(e))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(path: &str) -> Event<()> {
+        return Event::Change(vec![FileChangeEvent {
+            paths: vec![PathBuf::from(path)],
+            is_dir: false,
+        }]);
+    }
+
+    #[test]
+    fn changes_are_merged() {
+        let queue = Queue::new();
+        queue.push(change("a"));
+        queue.push(change("b"));
+        queue.push(Event::Other(()));
+        queue.push(change("c"));
+        queue.push(Event::Stop);
+        queue.close();
+
+        assert!(matches!(queue.pop(), Some(Event::Stop)));
+        match queue.pop() {
+            Some(Event::Change(events)) => assert_eq!(events.len(), 2),
+            _ => panic!("expected the changes to a and b"),
+        }
+        assert!(matches!(queue.pop(), Some(Event::Other(()))));
+        assert!(matches!(queue.pop(), Some(Event::Change(_))));
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn cancel_only_while_running() {
+        let cancel = Cancel::default();
+        assert!(!cancel.cancel());
+        cancel.arm();
+        assert!(!cancel.is_cancelled());
+        assert!(cancel.cancel());
+        assert!(cancel.is_cancelled());
+        cancel.arm();
+        assert!(!cancel.is_cancelled());
+        cancel.disarm();
+        assert!(!cancel.cancel());
+        cancel.stop();
+        assert!(!cancel.is_cancelled());
+        cancel.arm();
+        assert!(cancel.is_cancelled());
+        cancel.disarm();
+        assert!(!cancel.is_cancelled());
+    }
+}
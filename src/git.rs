@@ -0,0 +1,604 @@
+use std::io::{self, Error, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::orchestrator::{PathReverter, Remote, SyncResult, WorkingTree};
+
+fn git(root: &Path) -> Command {
+    let mut cmd = Command::new("git");
+    cmd.current_dir(root);
+    return cmd;
+}
+
+fn run_git(cmd: &mut Command) -> io::Result<std::process::Output> {
+    let output = cmd.output()?;
+    if !output.status.success() {
+        return Err(Error::other(format!(
+            "{:?} failed: {}",
+            cmd,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    return Ok(output);
+}
+
+pub fn head(root: &Path) -> io::Result<String> {
+    let out = run_git(git(root).args(["rev-parse", "HEAD"]))?;
+    return Ok(String::from_utf8_lossy(&out.stdout).trim().to_string());
+}
+
+// Added to every commit tcr makes, so we can tell them apart from the
+// developer's own
+pub const TRAILER: &str = "Tcr-Commit: true";
+
+// run_git, with `input` on stdin
+fn run_git_with_input(cmd: &mut Command, input: &[u8]) -> io::Result<std::process::Output> {
+    cmd.stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child = cmd.spawn()?;
+    child.stdin.take().unwrap().write_all(input)?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(Error::other(format!(
+            "{:?} failed: {}",
+            cmd,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    return Ok(output);
+}
+
+// Marks HEAD as made by tcr, along with any other trailers. Only the message
+// changes: anything staged since is left out of the commit.
+pub fn add_trailers(root: &Path, extra: &[String]) -> io::Result<()> {
+    let message = run_git(git(root).args(["log", "-1", "--format=%B"]))?;
+    let mut cmd = git(root);
+    cmd.arg("interpret-trailers");
+    for trailer in std::iter::once(TRAILER).chain(extra.iter().map(|t| t.as_str())) {
+        cmd.args(["--trailer", trailer]);
+    }
+    let message = run_git_with_input(&mut cmd, &message.stdout)?;
+    run_git_with_input(
+        git(root).args([
+            "commit",
+            "-q",
+            "--amend",
+            "--only",
+            "--no-verify",
+            "-F",
+            "-",
+        ]),
+        &message.stdout,
+    )?;
+    return Ok(());
+}
+
+pub fn is_tcr_commit(root: &Path, rev: &str) -> io::Result<bool> {
+    let out = run_git(git(root).args(["log", "-1", "--format=%(trailers:only,unfold)", rev]))?;
+    let trailers = String::from_utf8_lossy(&out.stdout);
+    return Ok(trailers.lines().any(|l| l.trim() == TRAILER));
+}
+
+// Drops the HEAD commit but keeps its changes in the working tree. Refuses
+// to if tcr didn't make it.
+pub fn undo_tcr_commit(root: &Path) -> io::Result<String> {
+    let head = head(root)?;
+    if !is_tcr_commit(root, &head)? {
+        return Err(Error::other(format!(
+            "HEAD ({}) isn't a commit made by tcr",
+            head
+        )));
+    }
+    run_git(git(root).args(["reset", "-q", "HEAD~1"]))?;
+    return Ok(head);
+}
+
+// Makes `dir` a worktree with `commit` checked out, creating it if need be.
+// Files the build leaves behind that git ignores are kept between checkouts.
+pub fn checkout_worktree(root: &Path, dir: &Path, commit: &str) -> io::Result<()> {
+    if !dir.exists() {
+        let dir = dir.to_string_lossy();
+        run_git(git(root).args(["worktree", "add", "-q", "--detach", "-f", &dir, commit]))?;
+        return Ok(());
+    }
+    run_git(git(dir).args(["checkout", "-q", "--detach", "-f", commit]))?;
+    run_git(git(dir).args(["clean", "-q", "-f", "-d"]))?;
+    return Ok(());
+}
+
+// Undoes `commit` with a new commit, leaving uncommitted changes be
+pub fn revert_commit(root: &Path, commit: &str) -> io::Result<()> {
+    run_git(git(root).args(["revert", "--no-edit", commit]))?;
+    return Ok(());
+}
+
+// Paths with uncommitted changes, including untracked files
+pub fn changed_files(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let out = run_git(git(root).args(["status", "--porcelain", "-z", "--untracked-files=all"]))?;
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let mut paths = vec![];
+    let mut entries = stdout.split('\0').filter(|e| !e.is_empty());
+    while let Some(entry) = entries.next() {
+        if entry.len() < 4 {
+            continue;
+        }
+        paths.push(root.join(&entry[3..]));
+        // Renames and copies are followed by the original path
+        if entry.starts_with('R') || entry.starts_with('C') {
+            if let Some(from) = entries.next() {
+                paths.push(root.join(from));
+            }
+        }
+    }
+    return Ok(paths);
+}
+
+// Restores changed paths with git: files that exist in HEAD are checked out
+// again, files that don't are removed. Anything else in the working tree is
+// left alone.
+pub struct GitPathReverter {
+    root: PathBuf,
+}
+
+impl GitPathReverter {
+    pub fn new(root: PathBuf) -> GitPathReverter {
+        return GitPathReverter { root };
+    }
+
+    // Whether `relative` is a file in HEAD. Directories aren't: checking one
+    // out would clobber every change under it, not just the ones we saw.
+    fn in_head(&self, relative: &Path) -> bool {
+        let spec = format!("HEAD:./{}", relative.to_string_lossy());
+        return git(&self.root)
+            .args(["cat-file", "-t", &spec])
+            .output()
+            .map(|o| o.status.success() && o.stdout.trim_ascii() == b"blob")
+            .unwrap_or(false);
+    }
+}
+
+impl PathReverter for GitPathReverter {
+    fn revert_paths(&mut self, paths: &[PathBuf]) -> io::Result<()> {
+        let mut tracked = vec![];
+        let mut untracked = vec![];
+        for path in paths {
+            // Reporting a revert that didn't happen is worse than failing
+            let relative = match path.strip_prefix(&self.root) {
+                Ok(r) => r,
+                Err(_) => {
+                    return Err(Error::other(format!(
+                        "{} is outside {}",
+                        path.display(),
+                        self.root.display()
+                    )))
+                }
+            };
+            if self.in_head(relative) {
+                tracked.push(relative.to_path_buf());
+            } else {
+                untracked.push(path.clone());
+            }
+        }
+
+        if !tracked.is_empty() {
+            run_git(
+                git(&self.root)
+                    .args(["checkout", "HEAD", "--"])
+                    .args(&tracked),
+            )?;
+        }
+
+        // Directories are only removed once empty, so we never delete files
+        // we didn't see change.
+        untracked.sort_by_key(|p| std::cmp::Reverse(p.components().count()));
+        for path in untracked {
+            if path.is_dir() {
+                let _ = std::fs::remove_dir(&path);
+            } else if path.exists() {
+                std::fs::remove_file(&path)?;
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+// Asks git whether anything tracked differs from HEAD, whatever language it
+// reports in
+pub struct GitWorkingTree {
+    root: PathBuf,
+}
+
+impl GitWorkingTree {
+    pub fn new(root: PathBuf) -> GitWorkingTree {
+        return GitWorkingTree { root };
+    }
+}
+
+impl WorkingTree for GitWorkingTree {
+    fn has_changes(&mut self) -> io::Result<bool> {
+        let status = git(&self.root)
+            .args(["diff", "--quiet", "HEAD", "--"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?;
+        return match status.code() {
+            Some(0) => Ok(false),
+            Some(1) => Ok(true),
+            _ => Err(Error::other(format!("git diff failed: {}", status))),
+        };
+    }
+}
+
+pub struct GitRemote {
+    root: PathBuf,
+    remote: String,
+    branch: String,
+}
+
+impl GitRemote {
+    // Tracks `branch` on `remote`, defaulting to the current branch.
+    pub fn new(root: PathBuf, remote: String, branch: Option<String>) -> io::Result<GitRemote> {
+        let branch = match branch {
+            Some(b) => b,
+            None => {
+                let out = run_git(git(&root).args(["rev-parse", "--abbrev-ref", "HEAD"]))?;
+                String::from_utf8_lossy(&out.stdout).trim().to_string()
+            }
+        };
+        return Ok(GitRemote {
+            root,
+            remote,
+            branch,
+        });
+    }
+
+    fn upstream(&self) -> String {
+        return format!("{}/{}", self.remote, self.branch);
+    }
+
+    // Whether a rebase was started and left unfinished
+    fn rebasing(&self) -> io::Result<bool> {
+        for dir in &["rebase-merge", "rebase-apply"] {
+            let out = run_git(git(&self.root).args(["rev-parse", "--git-path", dir]))?;
+            let path = self.root.join(String::from_utf8_lossy(&out.stdout).trim());
+            if path.exists() {
+                return Ok(true);
+            }
+        }
+        return Ok(false);
+    }
+}
+
+impl Remote for GitRemote {
+    fn pull_rebase(&mut self) -> io::Result<SyncResult> {
+        run_git(git(&self.root).args(["fetch", "-q", &self.remote, &self.branch]))?;
+        let up_to_date = git(&self.root)
+            .args(["merge-base", "--is-ancestor", &self.upstream(), "HEAD"])
+            .status()?
+            .success();
+        if up_to_date {
+            return Ok(SyncResult::UpToDate);
+        }
+
+        // Uncommitted changes are stashed for the rebase and put back after
+        let rebase = git(&self.root)
+            .args(["rebase", "-q", "--autostash", &self.upstream()])
+            .output()?;
+        if !rebase.status.success() {
+            if self.rebasing()? {
+                run_git(git(&self.root).args(["rebase", "--abort"]))?;
+            }
+            return Ok(SyncResult::Conflict);
+        }
+        return Ok(SyncResult::Rebased);
+    }
+
+    fn push(&mut self) -> io::Result<()> {
+        let refspec = format!("HEAD:{}", self.branch);
+        run_git(git(&self.root).args(["push", "-q", &self.remote, &refspec]))?;
+        return Ok(());
+    }
+
+    fn reset_to_upstream(&mut self) -> io::Result<()> {
+        // Unlike --hard, this keeps uncommitted changes, and refuses if it
+        // would have to overwrite any
+        run_git(git(&self.root).args(["reset", "-q", "--keep", &self.upstream()]))?;
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn init_repo(root: &Path) {
+        run_git(git(root).args(["init", "-q"])).unwrap();
+        run_git(git(root).args(["config", "user.name", "tcr"])).unwrap();
+        run_git(git(root).args(["config", "user.email", "tcr@example.com"])).unwrap();
+        fs::write(root.join("a"), "a").unwrap();
+        fs::write(root.join("dirty"), "dirty").unwrap();
+        run_git(git(root).args(["add", "a", "dirty"])).unwrap();
+        run_git(git(root).args(["commit", "-q", "-m", "initial"])).unwrap();
+    }
+
+    #[test]
+    fn test_revert_paths_only_touches_given_paths() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let root = tmp_dir.path().to_path_buf();
+        init_repo(&root);
+
+        fs::write(root.join("a"), "changed").unwrap();
+        fs::write(root.join("dirty"), "still dirty").unwrap();
+        fs::create_dir(root.join("new_dir")).unwrap();
+        fs::write(root.join("new_dir").join("b"), "b").unwrap();
+
+        let mut reverter = GitPathReverter::new(root.clone());
+        reverter
+            .revert_paths(&[
+                root.join("a"),
+                root.join("new_dir"),
+                root.join("new_dir").join("b"),
+            ])
+            .expect("revert to succeed");
+
+        assert_eq!(fs::read_to_string(root.join("a")).unwrap(), "a");
+        assert_eq!(
+            fs::read_to_string(root.join("dirty")).unwrap(),
+            "still dirty"
+        );
+        assert!(!root.join("new_dir").exists());
+    }
+
+    #[test]
+    fn test_revert_paths_restores_deleted_file() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let root = tmp_dir.path().to_path_buf();
+        init_repo(&root);
+
+        fs::remove_file(root.join("a")).unwrap();
+
+        let mut reverter = GitPathReverter::new(root.clone());
+        reverter
+            .revert_paths(&[root.join("a")])
+            .expect("revert to succeed");
+
+        assert_eq!(fs::read_to_string(root.join("a")).unwrap(), "a");
+    }
+
+    #[test]
+    fn test_revert_paths_leaves_directories_be() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let root = tmp_dir.path().to_path_buf();
+        init_repo(&root);
+        fs::create_dir(root.join("dir")).unwrap();
+        commit_file(&root, "dir/a", "a");
+        commit_file(&root, "dir/dirty", "dirty");
+
+        fs::write(root.join("dir").join("a"), "changed").unwrap();
+        fs::write(root.join("dir").join("dirty"), "still dirty").unwrap();
+
+        let mut reverter = GitPathReverter::new(root.clone());
+        reverter
+            .revert_paths(&[root.join("dir"), root.join("dir").join("a")])
+            .expect("revert to succeed");
+
+        assert_eq!(fs::read_to_string(root.join("dir").join("a")).unwrap(), "a");
+        assert_eq!(
+            fs::read_to_string(root.join("dir").join("dirty")).unwrap(),
+            "still dirty"
+        );
+    }
+
+    #[test]
+    fn test_revert_paths_outside_the_root_fails() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let root = tmp_dir.path().to_path_buf();
+        init_repo(&root);
+        fs::write(root.join("a"), "changed").unwrap();
+
+        let mut reverter = GitPathReverter::new(root.clone());
+        reverter
+            .revert_paths(&[root.join("a"), PathBuf::from("/elsewhere/b")])
+            .expect_err("revert to fail");
+        assert_eq!(fs::read_to_string(root.join("a")).unwrap(), "changed");
+    }
+
+    #[test]
+    fn test_working_tree_has_changes() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let root = tmp_dir.path().to_path_buf();
+        init_repo(&root);
+        let mut working_tree = GitWorkingTree::new(root.clone());
+        assert!(!working_tree.has_changes().unwrap());
+
+        // Untracked files aren't committed by `git commit -a` either
+        fs::write(root.join("new"), "new").unwrap();
+        assert!(!working_tree.has_changes().unwrap());
+
+        fs::write(root.join("a"), "changed").unwrap();
+        assert!(working_tree.has_changes().unwrap());
+        run_git(git(&root).args(["add", "a"])).unwrap();
+        assert!(working_tree.has_changes().unwrap());
+    }
+
+    fn rev_parse(root: &Path, rev: &str) -> String {
+        let out = run_git(git(root).args(["rev-parse", rev])).unwrap();
+        return String::from_utf8_lossy(&out.stdout).trim().to_string();
+    }
+
+    fn commit_file(root: &Path, name: &str, contents: &str) {
+        fs::write(root.join(name), contents).unwrap();
+        run_git(git(root).args(["add", name])).unwrap();
+        run_git(git(root).args(["commit", "-q", "-m", name])).unwrap();
+    }
+
+    // A bare "origin" with two clones of it, both on `main`
+    fn clones(tmp: &Path) -> (PathBuf, PathBuf) {
+        let origin = tmp.join("origin.git");
+        run_git(git(tmp).args(["init", "-q", "--bare", "-b", "main", "origin.git"])).unwrap();
+        let mut clones = vec![];
+        for name in &["a", "b"] {
+            run_git(git(tmp).args(["clone", "-q", origin.to_str().unwrap(), name])).unwrap();
+            let root = tmp.join(name);
+            run_git(git(&root).args(["checkout", "-q", "-B", "main"])).unwrap();
+            run_git(git(&root).args(["config", "user.name", "tcr"])).unwrap();
+            run_git(git(&root).args(["config", "user.email", "tcr@example.com"])).unwrap();
+            clones.push(root);
+        }
+        commit_file(&clones[0], "shared", "initial");
+        run_git(git(&clones[0]).args(["push", "-q", "origin", "main"])).unwrap();
+        run_git(git(&clones[1]).args(["pull", "-q", "origin", "main"])).unwrap();
+        return (clones.remove(0), clones.remove(0));
+    }
+
+    #[test]
+    fn test_remote_rebases_and_pushes() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let (a, b) = clones(tmp_dir.path());
+
+        commit_file(&b, "from_b", "b");
+        run_git(git(&b).args(["push", "-q", "origin", "main"])).unwrap();
+        commit_file(&a, "from_a", "a");
+
+        let mut remote = GitRemote::new(a.clone(), String::from("origin"), None).unwrap();
+        assert_eq!(remote.pull_rebase().unwrap(), SyncResult::Rebased);
+        assert!(a.join("from_b").exists());
+        remote.push().expect("push to succeed");
+        assert_eq!(rev_parse(&a, "HEAD"), rev_parse(&a, "origin/main"));
+
+        assert_eq!(remote.pull_rebase().unwrap(), SyncResult::UpToDate);
+    }
+
+    #[test]
+    fn test_remote_conflict_resets_to_upstream() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let (a, b) = clones(tmp_dir.path());
+
+        commit_file(&b, "shared", "from b");
+        run_git(git(&b).args(["push", "-q", "origin", "main"])).unwrap();
+        commit_file(&a, "shared", "from a");
+        let local = rev_parse(&a, "HEAD");
+
+        let mut remote = GitRemote::new(a.clone(), String::from("origin"), None).unwrap();
+        assert_eq!(remote.pull_rebase().unwrap(), SyncResult::Conflict);
+        assert_eq!(rev_parse(&a, "HEAD"), local);
+
+        fs::write(a.join("untouched"), "kept").unwrap();
+        remote.reset_to_upstream().expect("reset to succeed");
+        assert_eq!(rev_parse(&a, "HEAD"), rev_parse(&b, "HEAD"));
+        assert_eq!(fs::read_to_string(a.join("shared")).unwrap(), "from b");
+        assert_eq!(fs::read_to_string(a.join("untouched")).unwrap(), "kept");
+    }
+
+    #[test]
+    fn test_remote_rebases_a_dirty_tree() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let (a, b) = clones(tmp_dir.path());
+
+        commit_file(&b, "from_b", "b");
+        run_git(git(&b).args(["push", "-q", "origin", "main"])).unwrap();
+        commit_file(&a, "from_a", "a");
+        fs::write(a.join("shared"), "editing").unwrap();
+
+        let mut remote = GitRemote::new(a.clone(), String::from("origin"), None).unwrap();
+        assert_eq!(remote.pull_rebase().unwrap(), SyncResult::Rebased);
+        assert!(a.join("from_b").exists());
+        assert_eq!(fs::read_to_string(a.join("shared")).unwrap(), "editing");
+    }
+
+    #[test]
+    fn test_changed_files() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let root = tmp_dir.path().to_path_buf();
+        init_repo(&root);
+
+        fs::write(root.join("a"), "changed").unwrap();
+        fs::create_dir(root.join("new_dir")).unwrap();
+        fs::write(root.join("new_dir").join("b"), "b").unwrap();
+
+        let mut changed = changed_files(&root).unwrap();
+        changed.sort();
+        assert_eq!(
+            changed,
+            vec![root.join("a"), root.join("new_dir").join("b")]
+        );
+    }
+
+    #[test]
+    fn test_undo_tcr_commit_keeps_changes() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let root = tmp_dir.path().to_path_buf();
+        init_repo(&root);
+        let initial = head(&root).unwrap();
+
+        fs::write(root.join("a"), "changed").unwrap();
+        run_git(git(&root).args(["commit", "-q", "-am", "change"])).unwrap();
+        fs::write(root.join("dirty"), "staged later").unwrap();
+        run_git(git(&root).args(["add", "dirty"])).unwrap();
+        add_trailers(&root, &[String::from("Tcr-Tests: 1 passed, 0 failed")]).unwrap();
+        let out = run_git(git(&root).args(["diff", "--cached", "--name-only"])).unwrap();
+        assert_eq!(String::from_utf8_lossy(&out.stdout).trim(), "dirty");
+        let out =
+            run_git(git(&root).args(["log", "-1", "--format=%(trailers:only,unfold)"])).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&out.stdout).trim(),
+            "Tcr-Commit: true\nTcr-Tests: 1 passed, 0 failed"
+        );
+
+        let undone = undo_tcr_commit(&root).expect("undo to succeed");
+        assert_ne!(undone, initial);
+        assert_eq!(head(&root).unwrap(), initial);
+        assert_eq!(fs::read_to_string(root.join("a")).unwrap(), "changed");
+    }
+
+    #[test]
+    fn test_undo_refuses_other_commits() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let root = tmp_dir.path().to_path_buf();
+        init_repo(&root);
+
+        fs::write(root.join("a"), "changed").unwrap();
+        run_git(git(&root).args(["commit", "-q", "-am", "change"])).unwrap();
+        let commit = head(&root).unwrap();
+
+        undo_tcr_commit(&root).expect_err("Expected this to fail");
+        assert_eq!(head(&root).unwrap(), commit);
+    }
+
+    #[test]
+    fn test_checkout_worktree() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let root = tmp_dir.path().join("repo");
+        fs::create_dir(&root).unwrap();
+        init_repo(&root);
+        let first = head(&root).unwrap();
+        commit_file(&root, "a", "second");
+        let worktree = tmp_dir.path().join("worktree");
+
+        checkout_worktree(&root, &worktree, &first).unwrap();
+        assert_eq!(fs::read_to_string(worktree.join("a")).unwrap(), "a");
+        fs::write(worktree.join("leftover"), "x").unwrap();
+        checkout_worktree(&root, &worktree, &head(&root).unwrap()).unwrap();
+        assert_eq!(fs::read_to_string(worktree.join("a")).unwrap(), "second");
+        assert!(!worktree.join("leftover").exists());
+    }
+
+    #[test]
+    fn test_revert_commit() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let root = tmp_dir.path().to_path_buf();
+        init_repo(&root);
+        commit_file(&root, "a", "broken");
+        fs::write(root.join("dirty"), "still dirty").unwrap();
+
+        revert_commit(&root, &head(&root).unwrap()).unwrap();
+        assert_eq!(fs::read_to_string(root.join("a")).unwrap(), "a");
+        assert_eq!(
+            fs::read_to_string(root.join("dirty")).unwrap(),
+            "still dirty"
+        );
+    }
+}
@@ -0,0 +1,286 @@
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::git;
+use crate::orchestrator::{CycleResult, Phase, Recorder};
+use crate::test_results::TestResults;
+
+// How much of each phase's output we keep
+const OUTPUT_TAIL_LINES: usize = 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PhaseRecord {
+    pub phase: Phase,
+    // None if the command couldn't be run or was killed by a signal
+    pub exit_code: Option<i32>,
+    pub duration_ms: u128,
+    pub output_tail: String,
+    // What we could make of the test output
+    #[serde(default)]
+    pub tests: Option<TestResults>,
+}
+
+fn tail(out: &std::process::Output) -> String {
+    let mut lines: Vec<String> = vec![];
+    for stream in &[&out.stdout, &out.stderr] {
+        lines.extend(String::from_utf8_lossy(stream).lines().map(String::from));
+    }
+    let start = lines.len().saturating_sub(OUTPUT_TAIL_LINES);
+    return lines[start..].join("\n");
+}
+
+impl PhaseRecord {
+    pub fn new(
+        phase: Phase,
+        output: &io::Result<std::process::Output>,
+        duration: Duration,
+        tests: Option<TestResults>,
+    ) -> PhaseRecord {
+        let (exit_code, output_tail) = match output {
+            Ok(out) => (out.status.code(), tail(out)),
+            Err(e) => (None, e.to_string()),
+        };
+        return PhaseRecord {
+            phase,
+            exit_code,
+            duration_ms: duration.as_millis(),
+            output_tail,
+            tests,
+        };
+    }
+}
+
+// Everything that happened in one cycle
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CycleRecord {
+    pub timestamp: DateTime<Utc>,
+    pub paths: Vec<PathBuf>,
+    pub phases: Vec<PhaseRecord>,
+    // None if the cycle errored
+    pub result: Option<CycleResult>,
+    pub error: Option<String>,
+    pub reverted: bool,
+    pub commit: Option<String>,
+    // slow_test_cmd failed on the commit
+    #[serde(default)]
+    pub slow_tests_failed: bool,
+}
+
+// Appends cycles to a JSON Lines file
+pub struct HistoryFile {
+    path: PathBuf,
+    root: PathBuf,
+}
+
+impl HistoryFile {
+    pub fn new(path: PathBuf, root: PathBuf) -> HistoryFile {
+        return HistoryFile { path, root };
+    }
+}
+
+impl Recorder for HistoryFile {
+    fn record(&mut self, record: &CycleRecord) -> io::Result<()> {
+        let mut record = record.clone();
+        if record.result == Some(CycleResult::Committed) {
+            record.commit = git::head(&self.root).ok();
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(&record)?)?;
+        return Ok(());
+    }
+}
+
+// Marks the cycle that made `commit` as having failed the slow tests
+pub fn mark_slow_tests_failed(path: &Path, commit: &str) -> io::Result<()> {
+    let mut records = read(path)?;
+    for record in records.iter_mut() {
+        if record.commit.as_deref() == Some(commit) {
+            record.slow_tests_failed = true;
+        }
+    }
+    let mut lines = String::new();
+    for record in &records {
+        lines.push_str(&serde_json::to_string(record)?);
+        lines.push('\n');
+    }
+    return std::fs::write(path, lines);
+}
+
+pub fn read(path: &Path) -> io::Result<Vec<CycleRecord>> {
+    let file = std::fs::File::open(path)?;
+    let mut records = vec![];
+    for line in io::BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        records.push(serde_json::from_str(&line)?);
+    }
+    return Ok(records);
+}
+
+#[derive(Default)]
+pub struct Filter {
+    pub result: Option<CycleResult>,
+    // Only cycles where a changed path contains this
+    pub path: Option<String>,
+    // Only the most recent `limit` cycles
+    pub limit: Option<usize>,
+}
+
+impl Filter {
+    pub fn apply(&self, records: Vec<CycleRecord>) -> Vec<CycleRecord> {
+        let mut matching: Vec<CycleRecord> = records
+            .into_iter()
+            .filter(|r| self.result.is_none() || r.result == self.result)
+            .filter(|r| match &self.path {
+                Some(p) => r
+                    .paths
+                    .iter()
+                    .any(|changed| changed.to_string_lossy().contains(p.as_str())),
+                None => true,
+            })
+            .collect();
+        if let Some(limit) = self.limit {
+            let start = matching.len().saturating_sub(limit);
+            matching.drain(..start);
+        }
+        return matching;
+    }
+}
+
+pub fn format_record(record: &CycleRecord) -> String {
+    let result = match (&record.result, &record.error) {
+        (Some(r), _) => format!("{:?}", r),
+        (None, Some(e)) => format!("Error: {}", e),
+        (None, None) => String::from("Error"),
+    };
+    let outcome = match (&record.commit, record.reverted) {
+        (Some(sha), _) => sha.chars().take(7).collect(),
+        (None, true) => String::from("reverted"),
+        (None, false) => String::from("-"),
+    };
+    let mut tests = match record.phases.iter().find_map(|p| p.tests.as_ref()) {
+        Some(t) => format!("  [{}]", t),
+        None => String::new(),
+    };
+    if record.slow_tests_failed {
+        tests.push_str("  [slow tests failed]");
+    }
+    let paths: Vec<String> = record
+        .paths
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    return format!(
+        "{}  {:<11}  {:<8}  {}{}",
+        record
+            .timestamp
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M:%S"),
+        result,
+        outcome,
+        paths.join(", "),
+        tests
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(result: CycleResult, path: &str) -> CycleRecord {
+        return CycleRecord {
+            timestamp: Utc::now(),
+            paths: vec![PathBuf::from(path)],
+            phases: vec![],
+            result: Some(result),
+            error: None,
+            reverted: result != CycleResult::Committed,
+            commit: None,
+            slow_tests_failed: false,
+        };
+    }
+
+    #[test]
+    fn test_write_and_read() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let path = tmp_dir.path().join("history.jsonl");
+        let mut history = HistoryFile::new(path.clone(), tmp_dir.path().to_path_buf());
+
+        history
+            .record(&record(CycleResult::TestFailed, "src/a.rs"))
+            .unwrap();
+        history
+            .record(&record(CycleResult::BuildFailed, "src/b.rs"))
+            .unwrap();
+
+        let records = read(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].result, Some(CycleResult::BuildFailed));
+    }
+
+    #[test]
+    fn test_mark_slow_tests_failed() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let path = tmp_dir.path().join("history.jsonl");
+        let mut records = [
+            record(CycleResult::Committed, "src/a.rs"),
+            record(CycleResult::Committed, "src/b.rs"),
+        ];
+        records[0].commit = Some(String::from("aaa"));
+        records[1].commit = Some(String::from("bbb"));
+        let lines: Vec<String> = records
+            .iter()
+            .map(|r| serde_json::to_string(r).unwrap())
+            .collect();
+        std::fs::write(&path, lines.join("\n")).unwrap();
+
+        mark_slow_tests_failed(&path, "bbb").unwrap();
+        let records = read(&path).unwrap();
+        assert!(!records[0].slow_tests_failed);
+        assert!(records[1].slow_tests_failed);
+        assert!(format_record(&records[1]).ends_with("[slow tests failed]"));
+    }
+
+    #[test]
+    fn test_filter() {
+        let records = vec![
+            record(CycleResult::Committed, "src/a.rs"),
+            record(CycleResult::TestFailed, "src/b.rs"),
+            record(CycleResult::Committed, "src/b.rs"),
+            record(CycleResult::Committed, "src/c.rs"),
+        ];
+
+        let filter = Filter {
+            result: Some(CycleResult::Committed),
+            path: Some(String::from("b.rs")),
+            limit: None,
+        };
+        assert_eq!(filter.apply(records.clone()), vec![records[2].clone()]);
+
+        let filter = Filter {
+            limit: Some(2),
+            ..Filter::default()
+        };
+        assert_eq!(filter.apply(records.clone()), records[2..].to_vec());
+    }
+
+    #[test]
+    fn test_tail() {
+        let out = std::process::Command::new("seq")
+            .arg("30")
+            .output()
+            .unwrap();
+        let t = tail(&out);
+        assert!(t.starts_with("11\n"));
+        assert!(t.ends_with("30"));
+    }
+}
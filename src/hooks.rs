@@ -0,0 +1,271 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::events::{Reporter, TcrEvent};
+use crate::git;
+use crate::log::Logger;
+use crate::orchestrator::Phase;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Hook {
+    BeforeCycle,
+    // Tests passed, before committing
+    AfterGreen,
+    AfterCommit,
+    BeforeRevert,
+    AfterRevert,
+    OnError,
+}
+
+impl Hook {
+    fn name(self) -> &'static str {
+        return match self {
+            Hook::BeforeCycle => "before_cycle",
+            Hook::AfterGreen => "after_green",
+            Hook::AfterCommit => "after_commit",
+            Hook::BeforeRevert => "before_revert",
+            Hook::AfterRevert => "after_revert",
+            Hook::OnError => "on_error",
+        };
+    }
+}
+
+// Commands to run at points in every cycle
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct HooksConfig {
+    before_cycle: Option<String>,
+    after_green: Option<String>,
+    after_commit: Option<String>,
+    before_revert: Option<String>,
+    after_revert: Option<String>,
+    on_error: Option<String>,
+}
+
+impl HooksConfig {
+    pub fn into_commands(self) -> Vec<(Hook, String)> {
+        let hooks = vec![
+            (Hook::BeforeCycle, self.before_cycle),
+            (Hook::AfterGreen, self.after_green),
+            (Hook::AfterCommit, self.after_commit),
+            (Hook::BeforeRevert, self.before_revert),
+            (Hook::AfterRevert, self.after_revert),
+            (Hook::OnError, self.on_error),
+        ];
+        return hooks
+            .into_iter()
+            .filter_map(|(hook, cmd)| cmd.map(|c| (hook, c)))
+            .collect();
+    }
+}
+
+// Runs hooks as the orchestrator reports what it's doing. They run to
+// completion before tcr carries on, so before_revert sees the changes that
+// are about to be reverted.
+pub struct Hooks<'a> {
+    root: PathBuf,
+    commands: HashMap<Hook, Command>,
+    logger: &'a Logger,
+    // About the current cycle
+    paths: Vec<PathBuf>,
+    phase: Option<Phase>,
+    exit_code: Option<i32>,
+    // after_green has run. Limbo mode tests again after rebasing, which
+    // shouldn't run it a second time.
+    green: bool,
+}
+
+impl<'a> Hooks<'a> {
+    pub fn new(root: &Path, commands: HashMap<Hook, Command>, logger: &'a Logger) -> Hooks<'a> {
+        return Hooks {
+            root: root.to_path_buf(),
+            commands,
+            logger,
+            paths: vec![],
+            phase: None,
+            exit_code: None,
+            green: false,
+        };
+    }
+
+    fn env(&self, hook: Hook) -> Vec<(&'static str, String)> {
+        let paths: Vec<String> = self
+            .paths
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+        let phase = match self.phase {
+            Some(p) => serde_json::to_value(p)
+                .unwrap()
+                .as_str()
+                .unwrap()
+                .to_string(),
+            None => String::new(),
+        };
+        let exit_code = match self.exit_code {
+            Some(code) => code.to_string(),
+            None => String::new(),
+        };
+        let mut env = vec![
+            ("TCR_HOOK", String::from(hook.name())),
+            ("TCR_CHANGED_FILES", paths.join("\n")),
+            ("TCR_PHASE", phase),
+            ("TCR_EXIT_CODE", exit_code),
+        ];
+        if hook == Hook::AfterCommit {
+            env.push(("TCR_COMMIT", git::head(&self.root).unwrap_or_default()));
+        }
+        return env;
+    }
+
+    fn run(&mut self, hook: Hook, extra_env: &[(&str, &str)]) {
+        let env = self.env(hook);
+        let cmd = match self.commands.get_mut(&hook) {
+            Some(cmd) => cmd,
+            None => return,
+        };
+        // Keep stdout for --format json events
+        cmd.current_dir(&self.root)
+            .envs(env)
+            .stdin(Stdio::null())
+            .stdout(std::io::stderr());
+        for (k, v) in extra_env {
+            cmd.env(k, v);
+        }
+        self.logger.debug(format!("Running {} hook..", hook.name()));
+        match cmd.status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => self
+                .logger
+                .warn(format!("{} hook failed: {}", hook.name(), status)),
+            Err(e) => self
+                .logger
+                .warn(format!("Unable to run {} hook: {}", hook.name(), e)),
+        }
+    }
+}
+
+impl Reporter for Hooks<'_> {
+    fn report(&mut self, event: &TcrEvent) {
+        match event {
+            TcrEvent::CycleStarted { paths } => {
+                self.paths = paths.clone();
+                self.phase = None;
+                self.exit_code = None;
+                self.green = false;
+                self.run(Hook::BeforeCycle, &[]);
+            }
+            TcrEvent::PhaseFinished {
+                phase, exit_code, ..
+            } if *phase != Phase::Revert => {
+                self.phase = Some(*phase);
+                self.exit_code = *exit_code;
+                if *phase == Phase::Test && *exit_code == Some(0) && !self.green {
+                    self.green = true;
+                    self.run(Hook::AfterGreen, &[]);
+                }
+            }
+            TcrEvent::Committed => self.run(Hook::AfterCommit, &[]),
+            TcrEvent::Reverting { .. } => self.run(Hook::BeforeRevert, &[]),
+            TcrEvent::Reverted { .. } | TcrEvent::ResetToUpstream => {
+                self.run(Hook::AfterRevert, &[])
+            }
+            TcrEvent::Error { message } => self.run(Hook::OnError, &[("TCR_ERROR", message)]),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log::Level;
+
+    #[test]
+    fn hooks_get_the_cycle_in_their_environment() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let out = tmp_dir.path().join("out");
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(format!(
+            "echo \"$TCR_HOOK $TCR_PHASE $TCR_EXIT_CODE $TCR_CHANGED_FILES\" >> {}",
+            out.display()
+        ));
+        let mut commands = HashMap::new();
+        commands.insert(Hook::BeforeRevert, cmd);
+
+        let logger = Logger::new(Level::Off);
+        let mut hooks = Hooks::new(tmp_dir.path(), commands, &logger);
+        hooks.report(&TcrEvent::CycleStarted {
+            paths: vec![PathBuf::from("src/a.rs")],
+        });
+        hooks.report(&TcrEvent::PhaseFinished {
+            phase: Phase::Test,
+            success: false,
+            exit_code: Some(101),
+            duration_ms: 0,
+            stdout: String::new(),
+            stderr: String::new(),
+            error: None,
+            tests: None,
+            diagnostics: vec![],
+        });
+        hooks.report(&TcrEvent::Reverting { paths: vec![] });
+        // Not configured, so nothing happens
+        hooks.report(&TcrEvent::Reverted { paths: vec![] });
+
+        let contents = std::fs::read_to_string(&out).unwrap();
+        assert_eq!(contents, "before_revert test 101 src/a.rs\n");
+    }
+
+    fn passed(phase: Phase) -> TcrEvent {
+        return TcrEvent::PhaseFinished {
+            phase,
+            success: true,
+            exit_code: Some(0),
+            duration_ms: 0,
+            stdout: String::new(),
+            stderr: String::new(),
+            error: None,
+            tests: None,
+            diagnostics: vec![],
+        };
+    }
+
+    #[test]
+    fn after_green_runs_once_a_cycle() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let out = tmp_dir.path().join("out");
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg(format!("echo \"$TCR_HOOK\" >> {}", out.display()));
+        let mut commands = HashMap::new();
+        commands.insert(Hook::AfterGreen, cmd);
+
+        let logger = Logger::new(Level::Off);
+        let mut hooks = Hooks::new(tmp_dir.path(), commands, &logger);
+        hooks.report(&TcrEvent::CycleStarted { paths: vec![] });
+        hooks.report(&passed(Phase::Test));
+        hooks.report(&TcrEvent::Committed);
+        // Limbo mode testing again after rebasing
+        hooks.report(&TcrEvent::Rebased);
+        hooks.report(&passed(Phase::Test));
+
+        let contents = std::fs::read_to_string(&out).unwrap();
+        assert_eq!(contents, "after_green\n");
+    }
+
+    #[test]
+    fn test_into_commands() {
+        let config: HooksConfig =
+            serde_json::from_str(r#"{"after_commit": "./push.sh", "on_error": "say oops"}"#)
+                .unwrap();
+        assert_eq!(
+            config.into_commands(),
+            [
+                (Hook::AfterCommit, String::from("./push.sh")),
+                (Hook::OnError, String::from("say oops"))
+            ]
+        );
+    }
+}
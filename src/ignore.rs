@@ -3,10 +3,13 @@ use ignore::gitignore::Gitignore;
 #[allow(unused_imports)]
 use itertools::Itertools;
 
+#[derive(Clone)]
 pub struct Checker {
     root: std::path::PathBuf,
     gitignore: Option<Gitignore>,
     emacs_re: regex::Regex,
+    // Files tcr writes itself, like the history
+    own_files: Vec<std::path::PathBuf>,
 }
 
 impl Checker {
@@ -16,9 +19,16 @@ impl Checker {
             root,
             gitignore,
             emacs_re,
+            own_files: vec![],
         };
     }
 
+    // Writing to `path` won't start a cycle, or be reverted
+    pub fn ignore_file(&mut self, path: std::path::PathBuf) {
+        self.own_files.push(path);
+    }
+
+    #[allow(clippy::needless_borrow, clippy::single_match)]
     pub fn is_ignored(&mut self, event: FileChangeEvent) -> bool {
         let paths = event.paths;
         if paths.iter().all(|p| p.starts_with(self.root.join(".git"))) {
@@ -27,6 +37,9 @@ impl Checker {
         if paths.iter().all(|p| self.is_editor_file(&p)) {
             return true;
         }
+        if paths.iter().all(|p| self.own_files.contains(p)) {
+            return true;
+        }
         match &self.gitignore {
             Some(gi) => {
                 let is_dir = event.is_dir;
@@ -39,9 +52,23 @@ impl Checker {
         return false;
     }
 
+    pub fn is_path_ignored(&mut self, path: &std::path::Path, is_dir: bool) -> bool {
+        if path.starts_with(self.root.join(".git"))
+            || self.is_editor_file(path)
+            || self.own_files.iter().any(|f| f == path)
+        {
+            return true;
+        }
+        return match &self.gitignore {
+            Some(gi) => gi.matched_path_or_any_parents(path, is_dir).is_ignore(),
+            None => false,
+        };
+    }
+
     // emacs **/.#*
     // vim "**/4913", "**/*~", "**/.*.swp", "**/.*.swx", "**/.*.swo", "**/.*.swn"
-    fn is_editor_file(&mut self, path: &std::path::PathBuf) -> bool {
+    #[allow(clippy::single_match)]
+    fn is_editor_file(&mut self, path: &std::path::Path) -> bool {
         match path.extension() {
             Some(e) => {
                 let s = e.to_str().unwrap();
@@ -60,6 +87,12 @@ impl Checker {
 }
 
 #[cfg(test)]
+#[allow(
+    clippy::bool_assert_comparison,
+    clippy::unnecessary_to_owned,
+    clippy::single_match,
+    clippy::single_component_path_imports
+)]
 mod tests {
     use super::*;
     use std::fs::File;
@@ -236,4 +269,16 @@ mod tests {
 
         assert_eq!(checker.is_ignored(event), true);
     }
+
+    #[test]
+    fn test_own_files_are_ignored() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let mut checker = Checker::new(tmp_dir.path().to_path_buf(), None);
+        checker.ignore_file(tmp_dir.path().join("history.jsonl"));
+
+        let path = tmp_dir.path().join("history.jsonl");
+        assert_eq!(checker.is_path_ignored(&path, false), true);
+        let event = event_for_path(path);
+        assert_eq!(checker.is_ignored(event), true);
+    }
 }
@@ -0,0 +1,113 @@
+use std::io::{self, Read};
+use std::sync::mpsc::Sender;
+
+// Keyboard controls while watching
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Key {
+    Pause,
+    Run,
+    Commit,
+    Undo,
+    Quit,
+    Verbose,
+}
+
+impl Key {
+    pub fn from_char(c: char) -> Option<Key> {
+        return match c {
+            'p' => Some(Key::Pause),
+            'r' => Some(Key::Run),
+            'c' => Some(Key::Commit),
+            'u' => Some(Key::Undo),
+            'q' => Some(Key::Quit),
+            'v' => Some(Key::Verbose),
+            _ => None,
+        };
+    }
+}
+
+pub const HELP: &str = "p pause/resume, r run, c commit, u undo, v verbose, q quit";
+
+// Reads keys from stdin on a background thread, sending each one through
+// `f`. Stops at EOF or once the receiving end is gone.
+pub fn spawn_reader<T: Send + 'static>(tx: Sender<T>, f: fn(Key) -> T) {
+    std::thread::spawn(move || {
+        for byte in io::stdin().lock().bytes() {
+            let key = match byte {
+                Ok(b) => Key::from_char(b as char),
+                Err(_) => return,
+            };
+            if let Some(k) = key {
+                if tx.send(f(k)).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+}
+
+// Puts the terminal into non-canonical mode without echo, so keys arrive as
+// they're pressed. Output processing is left alone so println! still works.
+// The previous settings are restored on drop.
+#[cfg(unix)]
+pub struct RawMode {
+    saved: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    // None if stdin isn't a terminal
+    pub fn enable() -> io::Result<Option<RawMode>> {
+        unsafe {
+            if libc::isatty(libc::STDIN_FILENO) != 1 {
+                return Ok(None);
+            }
+            let mut saved: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut saved) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = saved;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            return Ok(Some(RawMode { saved }));
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved);
+        }
+    }
+}
+
+// Elsewhere keys still work, they just need Enter after them
+#[cfg(not(unix))]
+pub struct RawMode {}
+
+#[cfg(not(unix))]
+impl RawMode {
+    pub fn enable() -> io::Result<Option<RawMode>> {
+        return Ok(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_char() {
+        assert_eq!(Key::from_char('p'), Some(Key::Pause));
+        assert_eq!(Key::from_char('q'), Some(Key::Quit));
+        assert_eq!(Key::from_char('\n'), None);
+        assert_eq!(Key::from_char('x'), None);
+    }
+}
@@ -0,0 +1,40 @@
+#![allow(clippy::needless_return)]
+
+pub mod error;
+pub mod events;
+pub mod executor;
+pub mod orchestrator;
+pub mod watcher;
+
+pub(crate) mod affected;
+pub(crate) mod cargo;
+mod cli;
+pub(crate) mod compact;
+pub(crate) mod control;
+pub(crate) mod diagnostics;
+pub(crate) mod env;
+pub(crate) mod git;
+pub(crate) mod history;
+pub(crate) mod hooks;
+pub(crate) mod ignore;
+pub(crate) mod keys;
+pub(crate) mod log;
+pub(crate) mod notifications;
+pub(crate) mod parallel;
+pub(crate) mod server;
+pub(crate) mod slow_tests;
+pub(crate) mod stats;
+pub(crate) mod status;
+pub(crate) mod test_paths;
+pub(crate) mod test_results;
+
+pub use cli::run_cli;
+
+// Types the public modules use
+pub use diagnostics::Diagnostic;
+pub use history::{CycleRecord, PhaseRecord};
+pub use ignore::Checker;
+pub use log::{Level, Logger};
+pub use stats::{Stats, Summary};
+pub use test_paths::TestPaths;
+pub use test_results::TestResults;
@@ -1,14 +1,174 @@
-pub struct VerboseLogger {
-    verbose: bool,
+use chrono::Local;
+use regex::Regex;
+use std::cell::{Cell, RefCell};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Ordered from least to most verbose
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    // Only makes sense as a filter: log nothing
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
 }
 
-impl VerboseLogger {
-    pub fn new(verbose: bool) -> VerboseLogger {
-        return VerboseLogger { verbose };
+impl std::str::FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Level, String> {
+        return match s.trim().to_lowercase().as_str() {
+            "off" => Ok(Level::Off),
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            "trace" => Ok(Level::Trace),
+            other => Err(format!(
+                "unknown log level {:?}, expected off, error, warn, info, debug or trace",
+                other
+            )),
+        };
     }
-    pub fn log(&self, s: impl Into<String>) {
-        if self.verbose {
-            println!("{}", s.into());
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        return match self {
+            Level::Off => "OFF",
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+    }
+}
+
+// Everything tcr prints goes through here. Errors and warnings go to stderr,
+// the rest to stdout unless `all_to_stderr` is set. The log file, if there
+// is one, gets every message that passes the filter, timestamped and
+// without colours.
+pub struct Logger {
+    max_level: Cell<Level>,
+    timestamps: bool,
+    all_to_stderr: bool,
+    file: RefCell<Option<File>>,
+    file_path: Option<PathBuf>,
+    ansi: Regex,
+}
+
+impl Logger {
+    pub fn new(max_level: Level) -> Logger {
+        return Logger {
+            max_level: Cell::new(max_level),
+            timestamps: false,
+            all_to_stderr: false,
+            file: RefCell::new(None),
+            file_path: None,
+            ansi: Regex::new("\x1b\\[[0-9;]*m").unwrap(),
+        };
+    }
+
+    // Info by default, debug with --verbose. TCR_LOG overrides both.
+    pub fn from_env(verbose: bool) -> Logger {
+        let default = if verbose { Level::Debug } else { Level::Info };
+        let logger = Logger::new(default);
+        if let Ok(filter) = std::env::var("TCR_LOG") {
+            match filter.parse() {
+                Ok(level) => logger.max_level.set(level),
+                Err(e) => logger.warn(format!("Ignoring TCR_LOG: {}", e)),
+            }
+        }
+        return logger;
+    }
+
+    pub fn set_timestamps(&mut self, timestamps: bool) {
+        self.timestamps = timestamps;
+    }
+
+    pub fn set_all_to_stderr(&mut self, all_to_stderr: bool) {
+        self.all_to_stderr = all_to_stderr;
+    }
+
+    pub fn set_file(&mut self, path: &Path) -> io::Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.file = RefCell::new(Some(file));
+        self.file_path = Some(std::path::absolute(path)?);
+        return Ok(());
+    }
+
+    pub fn file_path(&self) -> Option<&Path> {
+        return self.file_path.as_deref();
+    }
+
+    pub fn enabled(&self, level: Level) -> bool {
+        return level != Level::Off && level <= self.max_level.get();
+    }
+
+    pub fn log(&self, level: Level, message: impl AsRef<str>) {
+        if !self.enabled(level) {
+            return;
+        }
+        let message = message.as_ref();
+        let console = if self.timestamps {
+            format!("[{}] {}", Local::now().format("%H:%M:%S"), message)
+        } else {
+            message.to_string()
+        };
+        if self.all_to_stderr || level <= Level::Warn {
+            eprintln!("{}", console);
+        } else {
+            println!("{}", console);
+        }
+
+        if let Some(file) = self.file.borrow_mut().as_mut() {
+            let plain = self.ansi.replace_all(message, "");
+            // Logging shouldn't take tcr down, so a failed write is dropped
+            let _ = writeln!(
+                file,
+                "{} {:<5} {}",
+                Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+                level.name(),
+                plain
+            );
+        }
+    }
+
+    pub fn error(&self, message: impl AsRef<str>) {
+        self.log(Level::Error, message);
+    }
+
+    pub fn warn(&self, message: impl AsRef<str>) {
+        self.log(Level::Warn, message);
+    }
+
+    pub fn info(&self, message: impl AsRef<str>) {
+        self.log(Level::Info, message);
+    }
+
+    pub fn debug(&self, message: impl AsRef<str>) {
+        self.log(Level::Debug, message);
+    }
+
+    pub fn trace(&self, message: impl AsRef<str>) {
+        self.log(Level::Trace, message);
+    }
+
+    pub fn is_verbose(&self) -> bool {
+        return self.enabled(Level::Debug);
+    }
+
+    // Switches between info and debug, leaving trace and quieter filters be
+    pub fn toggle_verbose(&self) {
+        match self.max_level.get() {
+            Level::Info => self.max_level.set(Level::Debug),
+            Level::Debug => self.max_level.set(Level::Info),
+            _ => {}
         }
     }
 }
@@ -16,11 +176,40 @@ impl VerboseLogger {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use colored::*;
+
+    #[test]
+    fn test_levels() {
+        let l = Logger::new(Level::Warn);
+        assert!(l.enabled(Level::Error));
+        assert!(l.enabled(Level::Warn));
+        assert!(!l.enabled(Level::Info));
+        assert!(!Logger::new(Level::Off).enabled(Level::Error));
+        assert_eq!("DEBUG".parse(), Ok(Level::Debug));
+        assert!("loud".parse::<Level>().is_err());
+    }
+
+    #[test]
+    fn test_toggle_verbose() {
+        let l = Logger::new(Level::Info);
+        l.toggle_verbose();
+        assert!(l.is_verbose());
+        l.toggle_verbose();
+        assert!(!l.is_verbose());
+    }
 
     #[test]
-    fn test_verbose_logger() {
-        let l = VerboseLogger { verbose: true };
-        l.log("Hello world");
-        l.log(String::from("foo"));
+    fn test_log_file() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let path = tmp_dir.path().join("tcr.log");
+        let mut l = Logger::new(Level::Info);
+        l.set_file(&path).unwrap();
+        l.info(format!("{}", "Tests passed".green()));
+        l.debug("filtered out");
+        drop(l);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 1);
+        assert!(contents.trim_end().ends_with("INFO  Tests passed"));
     }
 }
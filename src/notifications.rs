@@ -0,0 +1,227 @@
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crate::env;
+use crate::events::{Reporter, TcrEvent};
+use crate::log::Logger;
+use crate::orchestrator::CycleResult;
+
+// Terminal escape sequences that raise a desktop notification
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Escape {
+    // iTerm2, Windows Terminal, kitty, ...
+    Osc9,
+    // rxvt-unicode, foot, VTE based terminals, ...
+    Osc777,
+}
+
+impl Escape {
+    fn sequence(self, title: &str, body: &str) -> String {
+        return match self {
+            Escape::Osc9 => format!("\x1b]9;{}: {}\x07", title, body),
+            Escape::Osc777 => format!("\x1b]777;notify;{};{}\x07", title, body),
+        };
+    }
+}
+
+fn message(result: CycleResult, reverted: bool) -> String {
+    let message = match result {
+        CycleResult::Committed => "Green, changes committed",
+        CycleResult::BuildFailed => "Build failed",
+        CycleResult::TestFailed => "Tests failed",
+        CycleResult::FailingTestKept => "Tests failed, failing test kept",
+        CycleResult::NothingToCommit => "Green, nothing to commit",
+        CycleResult::SyncFailed => "Couldn't sync with upstream",
+    };
+    if reverted {
+        return format!("{}, changes reverted", message);
+    }
+    return String::from(message);
+}
+
+// Environment for notify_cmd
+fn env(result: CycleResult, reverted: bool, paths: &[PathBuf]) -> Vec<(&'static str, String)> {
+    let result_name = serde_json::to_value(result).unwrap();
+    let paths: Vec<String> = paths
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    return vec![
+        ("TCR_RESULT", result_name.as_str().unwrap().to_string()),
+        ("TCR_REVERTED", reverted.to_string()),
+        ("TCR_MESSAGE", message(result, reverted)),
+        ("TCR_CHANGED_FILES", paths.join("\n")),
+    ];
+}
+
+// Environment for notify_cmd when the slow tests fail
+fn slow_tests_env(commit: &str, reverted: bool) -> Vec<(&'static str, String)> {
+    let short: String = commit.chars().take(7).collect();
+    let mut message = format!("Slow tests failed on {}", short);
+    if reverted {
+        message.push_str(", commit reverted");
+    }
+    return vec![
+        ("TCR_RESULT", String::from("slow_tests_failed")),
+        ("TCR_REVERTED", reverted.to_string()),
+        ("TCR_MESSAGE", message),
+        ("TCR_COMMIT", commit.to_string()),
+    ];
+}
+
+// Lets you know how each cycle went when you're not looking at the terminal
+pub struct Notifier<'a> {
+    bell: bool,
+    escape: Option<Escape>,
+    cmd: Option<Command>,
+    logger: &'a Logger,
+}
+
+impl<'a> Notifier<'a> {
+    pub fn new(
+        bell: bool,
+        escape: Option<Escape>,
+        cmd: Option<Command>,
+        logger: &'a Logger,
+    ) -> Notifier<'a> {
+        return Notifier {
+            bell,
+            escape,
+            cmd,
+            logger,
+        };
+    }
+
+    fn notify(&mut self, env: Vec<(&'static str, String)>) {
+        let body = env
+            .iter()
+            .find(|(k, _)| *k == "TCR_MESSAGE")
+            .map(|(_, v)| v.clone())
+            .unwrap_or_default();
+        // stderr so the escapes don't end up amongst --format json events
+        let mut terminal = std::io::stderr();
+        if self.bell {
+            let _ = write!(terminal, "\x07");
+        }
+        if let Some(escape) = self.escape {
+            let _ = write!(terminal, "{}", escape.sequence("tcr", &body));
+        }
+        let _ = terminal.flush();
+
+        if let Some(template) = &self.cmd {
+            // A fresh one each time, so variables like TCR_COMMIT don't carry
+            // over to the next notification. Its output goes to stderr too,
+            // and it mustn't read the keys meant for us.
+            let mut cmd = env::copy(template);
+            cmd.envs(env).stdin(Stdio::null()).stdout(std::io::stderr());
+            match cmd.spawn() {
+                // Reap it in the background rather than hold up the next cycle
+                Ok(mut child) => {
+                    std::thread::spawn(move || child.wait());
+                }
+                Err(e) => self.logger.warn(format!("Error running notify_cmd: {}", e)),
+            }
+        }
+    }
+}
+
+impl Reporter for Notifier<'_> {
+    fn report(&mut self, event: &TcrEvent) {
+        match event {
+            TcrEvent::CycleFinished {
+                result,
+                paths,
+                reverted,
+            } => self.notify(env(*result, *reverted, paths)),
+            // Passing slow tests aren't worth interrupting you for
+            TcrEvent::SlowTestsFinished {
+                commit,
+                success: false,
+                reverted,
+                ..
+            } => self.notify(slow_tests_env(commit, *reverted)),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(Escape::Osc9.sequence("tcr", "hi"), "\x1b]9;tcr: hi\x07");
+        assert_eq!(
+            Escape::Osc777.sequence("tcr", "hi"),
+            "\x1b]777;notify;tcr;hi\x07"
+        );
+    }
+
+    #[test]
+    fn each_notification_gets_its_own_environment() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let out = tmp_dir.path().join("out");
+        let mut cmd = Command::new("sh");
+        cmd.args([
+            "-c",
+            &format!("echo \"[$TCR_COMMIT]\" >> {}", out.display()),
+        ]);
+        let logger = Logger::new(crate::log::Level::Off);
+        let mut notifier = Notifier::new(false, None, Some(cmd), &logger);
+
+        notifier.report(&TcrEvent::SlowTestsFinished {
+            commit: String::from("abc1234"),
+            success: false,
+            exit_code: Some(1),
+            duration_ms: 0,
+            stdout: String::new(),
+            stderr: String::new(),
+            error: None,
+            tests: None,
+            reverted: false,
+        });
+        // The notifications run in the background, one at a time here
+        let wait_for = |lines: usize| {
+            for _ in 0..500 {
+                let contents = std::fs::read_to_string(&out).unwrap_or_default();
+                if contents.lines().count() >= lines {
+                    return contents;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            panic!("notify_cmd didn't run");
+        };
+        wait_for(1);
+        notifier.report(&TcrEvent::CycleFinished {
+            result: CycleResult::Committed,
+            paths: vec![],
+            reverted: false,
+        });
+        assert_eq!(wait_for(2), "[abc1234]\n[]\n");
+    }
+
+    #[test]
+    fn test_env() {
+        let env = env(
+            CycleResult::TestFailed,
+            true,
+            &[PathBuf::from("a.rs"), PathBuf::from("b.rs")],
+        );
+        assert_eq!(
+            env,
+            [
+                ("TCR_RESULT", String::from("test_failed")),
+                ("TCR_REVERTED", String::from("true")),
+                (
+                    "TCR_MESSAGE",
+                    String::from("Tests failed, changes reverted")
+                ),
+                ("TCR_CHANGED_FILES", String::from("a.rs\nb.rs")),
+            ]
+        );
+    }
+}
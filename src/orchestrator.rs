@@ -1,12 +1,21 @@
-use colored::*;
-use itertools::Itertools;
 use notify::Event;
 use notify::EventKind;
-use std::io::{self, Error, ErrorKind};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::io;
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
+use crate::diagnostics;
+use crate::error::{Result, TcrError};
+use crate::events::{Reporter, TcrEvent, TextReporter};
+use crate::executor::Cancel;
+use crate::history::{CycleRecord, PhaseRecord};
 use crate::ignore::Checker;
-use crate::log::VerboseLogger;
+use crate::log::Logger;
+use crate::stats::Stats;
+use crate::test_paths::TestPaths;
+use crate::test_results::{self, TestResults};
 
 #[derive(Clone)]
 pub struct FileChangeEvent {
@@ -15,6 +24,7 @@ pub struct FileChangeEvent {
 }
 
 impl FileChangeEvent {
+    #[allow(clippy::collapsible_match, clippy::match_like_matches_macro)]
     pub fn new(event: Event) -> FileChangeEvent {
         let is_directory = match event.kind {
             EventKind::Create(e) => match e {
@@ -40,68 +50,121 @@ pub trait Runner {
     fn run(&mut self) -> io::Result<std::process::Output>;
 }
 
+// Restores a specific set of paths to their state at the last commit, as an
+// alternative to running the configured revert command over the whole tree.
+#[mockall::automock]
+pub trait PathReverter {
+    fn revert_paths(&mut self, paths: &[PathBuf]) -> io::Result<()>;
+}
+
+// What to do when the tests fail
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum FailurePolicy {
+    Revert,
+    // Don't revert if only test files changed: you just wrote a failing test
+    Relaxed,
+    // Revert everything but the tests, so the failing test survives for the
+    // next attempt. Needs a PathReverter.
+    KeepTests,
+}
+
+impl Default for FailurePolicy {
+    fn default() -> Self {
+        return FailurePolicy::Revert;
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    Build,
+    Test,
+    Commit,
+    Revert,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum CycleResult {
+    Committed,
+    BuildFailed,
+    TestFailed,
+    // The tests failed but the relaxed failure policy kept the change
+    FailingTestKept,
+    // Green, but the commit command found nothing to commit, e.g. a file was
+    // saved without changing it
+    NothingToCommit,
+    // Limbo mode couldn't push the commit, so it was thrown away
+    SyncFailed,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SyncResult {
+    UpToDate,
+    Rebased,
+    // The rebase was aborted, leaving the local branch as it was
+    Conflict,
+}
+
+// The upstream branch used by limbo mode: after each commit we rebase onto it
+// and push.
+#[mockall::automock]
+pub trait Remote {
+    fn pull_rebase(&mut self) -> io::Result<SyncResult>;
+    fn push(&mut self) -> io::Result<()>;
+    // Throws away local commits that haven't been pushed
+    fn reset_to_upstream(&mut self) -> io::Result<()>;
+}
+
+// Whether there's anything to commit. Asked when the commit command fails,
+// as git does when a file was saved without changing it.
+#[mockall::automock]
+pub trait WorkingTree {
+    fn has_changes(&mut self) -> io::Result<bool>;
+}
+
+// Somewhere to keep a record of every cycle
+#[mockall::automock]
+pub trait Recorder {
+    fn record(&mut self, record: &CycleRecord) -> io::Result<()>;
+}
+
 pub struct Orchestrator<'a> {
     ignore: Checker,
     build: &'a mut dyn Runner,
     test: &'a mut dyn Runner,
     commit: &'a mut dyn Runner,
     revert: &'a mut dyn Runner,
-    logger: &'a VerboseLogger,
+    path_reverter: Option<&'a mut dyn PathReverter>,
+    remote: Option<&'a mut dyn Remote>,
+    working_tree: Option<&'a mut dyn WorkingTree>,
+    history: Option<&'a mut dyn Recorder>,
+    failure_policy: FailurePolicy,
+    test_paths: Option<TestPaths>,
+    // A file the test command writes its results to, e.g. JUnit XML
+    test_report: Option<PathBuf>,
+    // Armed while the build and tests run, so they can be cancelled
+    cancel: Option<Cancel>,
+    reporters: Vec<Box<dyn Reporter + 'a>>,
     last_run: Option<Instant>,
     delay: Duration,
+    // Paths changed since the last commit (or revert)
+    changed_paths: BTreeSet<PathBuf>,
+    stats: Stats,
+    // What's happened so far in the current cycle
+    phases: Vec<PhaseRecord>,
+    reverted: bool,
 }
 
-fn print_output(out: &std::process::Output) {
-    let utf_string = std::str::from_utf8(&out.stdout);
-    match utf_string {
-        Ok(s) => {
-            if s.len() != 0 {
-                println!("{}", s)
-            }
-        }
-        Err(e) => eprintln!("Error handlng process stdout: {:#?}", e),
-    }
-
-    let utf_string = std::str::from_utf8(&out.stderr);
-    match utf_string {
-        Ok(s) => {
-            if s.len() != 0 {
-                println!("{}", s)
-            }
-        }
-        Err(e) => eprintln!("Error handlng process stderr: {:#?}", e),
-    }
-}
-
-fn handle_output(
-    output: std::result::Result<std::process::Output, std::io::Error>,
-) -> Option<std::io::Error> {
-    match output {
-        Ok(res) => {
-            print_output(&res);
-
-            if !res.status.success() {
-                return Some(Error::new(
-                    ErrorKind::Other,
-                    "cmd returned non-zero exit code",
-                ));
-            }
-            return None;
-        }
-        Err(e) => {
-            return Some(e);
-        }
-    }
-}
-
-impl Orchestrator<'_> {
-    pub fn new<'a>(
+impl<'a> Orchestrator<'a> {
+    pub fn new(
         ignore: Checker,
         build: &'a mut dyn Runner,
         test: &'a mut dyn Runner,
         commit: &'a mut dyn Runner,
         revert: &'a mut dyn Runner,
-        logger: &'a VerboseLogger,
+        logger: &'a Logger,
     ) -> Orchestrator<'a> {
         let delay = Duration::from_secs(1);
         return Orchestrator {
@@ -110,11 +173,94 @@ impl Orchestrator<'_> {
             test,
             commit,
             revert,
-            logger,
+            path_reverter: None,
+            remote: None,
+            working_tree: None,
+            history: None,
+            failure_policy: FailurePolicy::Revert,
+            test_paths: None,
+            test_report: None,
+            cancel: None,
+            reporters: vec![Box::new(TextReporter::new(logger))],
             last_run: None,
             delay,
+            changed_paths: BTreeSet::new(),
+            stats: Stats::default(),
+            phases: vec![],
+            reverted: false,
         };
     }
+
+    // When set, failures only revert the paths changed since the last commit
+    // rather than running the revert command.
+    pub fn set_path_reverter(&mut self, path_reverter: &'a mut dyn PathReverter) {
+        self.path_reverter = Some(path_reverter);
+    }
+
+    // Enables limbo mode: every commit is rebased onto the remote, tested
+    // again and pushed.
+    pub fn set_remote(&mut self, remote: &'a mut dyn Remote) {
+        self.remote = Some(remote);
+    }
+
+    // Lets a commit command that fails because there was nothing to commit
+    // count as green. Without one, it's a failed commit.
+    pub fn set_working_tree(&mut self, working_tree: &'a mut dyn WorkingTree) {
+        self.working_tree = Some(working_tree);
+    }
+
+    // Replaces the default text output
+    pub fn set_reporter(&mut self, reporter: Box<dyn Reporter + 'a>) {
+        self.reporters = vec![reporter];
+    }
+
+    // Reports events somewhere else as well
+    pub fn add_reporter(&mut self, reporter: Box<dyn Reporter + 'a>) {
+        self.reporters.push(reporter);
+    }
+
+    pub fn report(&mut self, event: TcrEvent) {
+        for reporter in self.reporters.iter_mut() {
+            reporter.report(&event);
+        }
+    }
+
+    pub fn set_test_report(&mut self, path: PathBuf) {
+        self.test_report = Some(path);
+    }
+
+    // Lets the build and tests be cancelled. The commit, revert and syncing
+    // with the remote always run to the end.
+    pub fn set_cancel(&mut self, cancel: Cancel) {
+        self.cancel = Some(cancel);
+    }
+
+    pub fn set_history(&mut self, history: &'a mut dyn Recorder) {
+        self.history = Some(history);
+    }
+
+    pub fn set_failure_policy(&mut self, policy: FailurePolicy, test_paths: TestPaths) {
+        self.failure_policy = policy;
+        self.test_paths = Some(test_paths);
+    }
+
+    fn only_tests_changed(&self) -> bool {
+        return match &self.test_paths {
+            Some(test_paths) => {
+                !self.changed_paths.is_empty()
+                    && self.changed_paths.iter().all(|p| test_paths.is_test(p))
+            }
+            None => false,
+        };
+    }
+
+    fn record_changed_paths(&mut self, event: &FileChangeEvent) {
+        for path in &event.paths {
+            if !self.ignore.is_path_ignored(path, event.is_dir) {
+                self.changed_paths.insert(path.clone());
+            }
+        }
+    }
     fn should_debounce(&mut self) -> bool {
         if self.last_run.is_some() {
             let then = self.last_run.unwrap();
@@ -128,77 +274,405 @@ impl Orchestrator<'_> {
 
         return false;
     }
-    pub fn handle_event(
-        &mut self,
-        event: FileChangeEvent,
-    ) -> std::result::Result<(), std::io::Error> {
-        let paths_str: String = event
-            .paths
-            .iter()
-            .map(|p| p.to_str().unwrap())
-            .intersperse(", ")
-            .collect();
-        if self.ignore.is_ignored(event) {
-            self.logger
-                .log(format!("{} {}", "Files are ignored: ".yellow(), paths_str));
-            return Ok(());
+    // Returns the result of the cycle, or None if the event was ignored or
+    // debounced.
+    pub fn handle_event(&mut self, event: FileChangeEvent) -> Result<Option<CycleResult>> {
+        return self.handle_events(vec![event]);
+    }
+
+    // Like handle_event, but for changes that piled up while a cycle was
+    // running. They're all handled by one cycle.
+    pub fn handle_events(&mut self, events: Vec<FileChangeEvent>) -> Result<Option<CycleResult>> {
+        let mut paths = vec![];
+        for event in events {
+            self.record_changed_paths(&event);
+            if self.ignore.is_ignored(event.clone()) {
+                self.report(TcrEvent::Ignored { paths: event.paths });
+            } else {
+                for path in event.paths {
+                    if !paths.contains(&path) {
+                        paths.push(path);
+                    }
+                }
+            }
+        }
+        if paths.is_empty() {
+            return Ok(None);
         } else if self.should_debounce() {
-            self.logger.log(format!(
-                "{}",
-                "Debouncing. Change occurred to close to last change".yellow()
-            ));
-            return Ok(());
+            self.report(TcrEvent::Debounced { paths });
+            return Ok(None);
         }
         self.last_run = Some(Instant::now());
-        println!("{}: {}", "Saw file changes".yellow(), paths_str);
-
-        println!("Running build..");
-        let build = self.build.run();
-        match handle_output(build) {
-            Some(err) => {
-                println!("{}: {:?}", "Build failed".red(), err);
-                let res = self.run_revert();
-                if res.is_err() {
-                    let err = res.err();
-                    return Err(err.unwrap());
-                }
-                return Ok(());
+        self.report(TcrEvent::ChangeSeen { paths });
+
+        return self.run_cycle().map(Some);
+    }
+
+    // Remembers changes without running a cycle for them, e.g. while paused,
+    // so a later revert still covers them
+    pub fn record_events(&mut self, events: &[FileChangeEvent]) {
+        for event in events {
+            self.record_changed_paths(event);
+        }
+    }
+
+    // Runs a single cycle as if `paths` had just changed, skipping the ignore
+    // and debounce checks.
+    pub fn run_once(&mut self, paths: Vec<PathBuf>) -> Result<CycleResult> {
+        self.record_changed_paths(&FileChangeEvent {
+            paths,
+            is_dir: false,
+        });
+        return self.run_cycle();
+    }
+
+    // Commits whatever has changed without building or testing it
+    pub fn force_commit(&mut self) -> Result<CycleResult> {
+        let result = self.commit()?;
+        if result == CycleResult::Committed {
+            self.stats.record_commit();
+        }
+        return Ok(result);
+    }
+
+    pub fn stats(&self) -> &Stats {
+        return &self.stats;
+    }
+
+    fn run_cycle(&mut self) -> Result<CycleResult> {
+        let timestamp = chrono::Utc::now();
+        let paths: Vec<PathBuf> = self.changed_paths.iter().cloned().collect();
+        self.phases.clear();
+        self.reverted = false;
+        self.report(TcrEvent::CycleStarted {
+            paths: paths.clone(),
+        });
+
+        let result = self.run_phases();
+        if let Err(TcrError::Cancelled { .. }) = result {
+            // Whatever cancelled it will start another cycle, which shouldn't
+            // be debounced. Nothing happened worth keeping a record of.
+            self.last_run = None;
+            return result;
+        }
+        if let Ok(r) = result {
+            self.stats.record_cycle(r);
+            self.report(TcrEvent::CycleFinished {
+                result: r,
+                paths: paths.clone(),
+                reverted: self.reverted,
+            });
+        }
+
+        if let Some(history) = &mut self.history {
+            let record = CycleRecord {
+                timestamp,
+                paths,
+                phases: std::mem::take(&mut self.phases),
+                result: result.as_ref().ok().copied(),
+                error: result.as_ref().err().map(|e| e.to_string()),
+                reverted: self.reverted,
+                commit: None,
+                slow_tests_failed: false,
+            };
+            if let Err(e) = history.record(&record) {
+                self.report(TcrEvent::Error {
+                    message: format!("Error writing history: {}", e),
+                });
             }
-            None => println!("{}", "Build succeeded".green()),
         }
-        let test = self.test.run();
-        match handle_output(test) {
-            Some(err) => {
-                println!("{}: {:?}", "Test failed".red(), err);
-                let res = self.run_revert();
-                if res.is_err() {
-                    let err = res.err();
-                    return Err(err.unwrap());
+        return result;
+    }
+
+    // From the test report file if there is one, otherwise the output
+    fn test_results(&self, out: &std::process::Output) -> Option<TestResults> {
+        if let Some(path) = &self.test_report {
+            return std::fs::read_to_string(path)
+                .ok()
+                .and_then(|report| test_results::parse(&report));
+        }
+        let output = format!(
+            "{}\n{}",
+            String::from_utf8_lossy(&out.stdout),
+            String::from_utf8_lossy(&out.stderr)
+        );
+        return test_results::parse(&output);
+    }
+
+    // Runs the command for `phase`, timing it and keeping a record of it for
+    // the history
+    fn run_phase(&mut self, phase: Phase) -> io::Result<std::process::Output> {
+        if let (Phase::Test, Some(path)) = (phase, &self.test_report) {
+            // So a report left by an earlier run isn't taken for this one's
+            let _ = std::fs::remove_file(path);
+        }
+        self.report(TcrEvent::PhaseStarted { phase });
+        let start = Instant::now();
+        let output = match phase {
+            Phase::Build => self.build.run(),
+            Phase::Test => self.test.run(),
+            Phase::Commit => self.commit.run(),
+            Phase::Revert => self.revert.run(),
+        };
+        let duration = start.elapsed();
+        self.stats.record_duration(phase, duration);
+        let tests = match (phase, &output) {
+            (Phase::Test, Ok(out)) => self.test_results(out),
+            _ => None,
+        };
+        let diagnostics = match (phase, &output) {
+            (Phase::Build, Ok(out)) if !out.status.success() => diagnostics::parse(&format!(
+                "{}\n{}",
+                String::from_utf8_lossy(&out.stdout),
+                String::from_utf8_lossy(&out.stderr)
+            )),
+            _ => vec![],
+        };
+        self.phases
+            .push(PhaseRecord::new(phase, &output, duration, tests.clone()));
+        let finished = match &output {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => TcrEvent::Cancelled { phase },
+            Ok(out) => TcrEvent::PhaseFinished {
+                phase,
+                success: out.status.success(),
+                exit_code: out.status.code(),
+                duration_ms: duration.as_millis() as u64,
+                stdout: String::from_utf8_lossy(&out.stdout).to_string(),
+                stderr: String::from_utf8_lossy(&out.stderr).to_string(),
+                error: None,
+                tests,
+                diagnostics,
+            },
+            Err(e) => TcrEvent::PhaseFinished {
+                phase,
+                success: false,
+                exit_code: None,
+                duration_ms: duration.as_millis() as u64,
+                stdout: String::new(),
+                stderr: String::new(),
+                error: Some(e.to_string()),
+                tests: None,
+                diagnostics: vec![],
+            },
+        };
+        self.report(finished);
+        return output;
+    }
+
+    // Runs the build or tests, returning whether they failed. Exiting
+    // non-zero, being killed (e.g. a segfault) and timing out (e.g. an
+    // infinite loop) are all failures worth reverting for. A command that
+    // couldn't run or was cancelled is an error instead, and the changes are
+    // left alone.
+    fn check(&mut self, phase: Phase) -> Result<bool> {
+        let output = self.run_phase(phase);
+        return match TcrError::from_output(phase, &output) {
+            Some(TcrError::NonZeroExit { .. })
+            | Some(TcrError::Signaled { .. })
+            | Some(TcrError::Timeout { .. }) => Ok(true),
+            Some(e) => Err(e),
+            None => Ok(false),
+        };
+    }
+
+    // The phase that failed, if either did
+    fn build_and_test(&mut self) -> Result<Option<Phase>> {
+        if self.check(Phase::Build)? {
+            return Ok(Some(Phase::Build));
+        }
+        if self.check(Phase::Test)? {
+            return Ok(Some(Phase::Test));
+        }
+        return Ok(None);
+    }
+
+    fn run_phases(&mut self) -> Result<CycleResult> {
+        if let Some(cancel) = &self.cancel {
+            cancel.arm();
+        }
+        let failed = self.build_and_test();
+        if let Some(cancel) = &self.cancel {
+            cancel.disarm();
+        }
+        let failed = failed?;
+        if failed == Some(Phase::Build) {
+            self.run_revert()?;
+            self.stats.record_revert(Phase::Build);
+            return Ok(CycleResult::BuildFailed);
+        }
+        if failed == Some(Phase::Test) {
+            if self.failure_policy == FailurePolicy::Relaxed && self.only_tests_changed() {
+                let paths = self.changed_paths.iter().cloned().collect();
+                self.report(TcrEvent::FailingTestKept { paths });
+                return Ok(CycleResult::FailingTestKept);
+            }
+            if self.failure_policy == FailurePolicy::KeepTests && self.path_reverter.is_some() {
+                self.revert_production_code()?;
+                self.stats.record_revert(Phase::Test);
+                return Ok(CycleResult::TestFailed);
+            }
+            self.run_revert()?;
+            self.stats.record_revert(Phase::Test);
+            return Ok(CycleResult::TestFailed);
+        }
+
+        if self.commit()? == CycleResult::NothingToCommit {
+            return Ok(CycleResult::NothingToCommit);
+        }
+        if self.remote.is_some() {
+            return self.sync_with_remote();
+        }
+        return Ok(CycleResult::Committed);
+    }
+
+    // Committed, or NothingToCommit
+    fn commit(&mut self) -> Result<CycleResult> {
+        let commit = self.run_phase(Phase::Commit);
+        if let Ok(out) = &commit {
+            if !out.status.success() && self.nothing_to_commit() {
+                self.changed_paths.clear();
+                self.report(TcrEvent::NothingToCommit);
+                return Ok(CycleResult::NothingToCommit);
+            }
+        }
+        if let Some(e) = TcrError::from_output(Phase::Commit, &commit) {
+            let e = TcrError::CommitFailed {
+                reason: e.to_string(),
+            };
+            self.report(TcrEvent::Error {
+                message: e.to_string(),
+            });
+            return Err(e);
+        }
+        self.changed_paths.clear();
+        self.report(TcrEvent::Committed);
+        return Ok(CycleResult::Committed);
+    }
+
+    fn sync_with_remote(&mut self) -> Result<CycleResult> {
+        self.report(TcrEvent::Rebasing);
+        let remote = self.remote.as_mut().unwrap();
+        match remote.pull_rebase()? {
+            SyncResult::UpToDate => {}
+            SyncResult::Rebased => {
+                self.report(TcrEvent::Rebased);
+                match self.check(Phase::Test) {
+                    Ok(false) => {}
+                    Ok(true) => {
+                        self.reset_to_upstream()?;
+                        return Ok(CycleResult::SyncFailed);
+                    }
+                    // Don't push what we couldn't test
+                    Err(e) => {
+                        self.reset_to_upstream()?;
+                        return Err(e);
+                    }
                 }
-                return Ok(());
             }
-            None => println!("{}", "Tests passed".green()),
+            SyncResult::Conflict => {
+                self.report(TcrEvent::RebaseConflict);
+                self.reset_to_upstream()?;
+                return Ok(CycleResult::SyncFailed);
+            }
         }
 
-        let commit = self.commit.run();
-        match commit {
-            Ok(_res) => println!("{}", "Changes committed".green()),
+        let remote = self.remote.as_mut().unwrap();
+        match remote.push() {
+            Ok(()) => self.report(TcrEvent::Pushed),
             Err(e) => {
-                eprintln!("{}", "Error comitting changes".red());
-                return Err(e);
+                self.report(TcrEvent::PushFailed {
+                    error: e.to_string(),
+                });
+                self.reset_to_upstream()?;
+                return Ok(CycleResult::SyncFailed);
             }
         }
-        return Ok(());
+        return Ok(CycleResult::Committed);
+    }
+
+    // After the commit command failed: whether it was because there was
+    // nothing to commit, which isn't a failure
+    fn nothing_to_commit(&mut self) -> bool {
+        return match &mut self.working_tree {
+            Some(working_tree) => matches!(working_tree.has_changes(), Ok(false)),
+            None => false,
+        };
     }
 
-    fn run_revert(&mut self) -> io::Result<std::process::Output> {
-        let revert_res = self.revert.run();
+    // Reports that reverting failed and returns the error to pass on
+    fn revert_failed(&mut self, reason: impl ToString) -> TcrError {
+        let e = TcrError::RevertFailed {
+            reason: reason.to_string(),
+        };
+        self.report(TcrEvent::Error {
+            message: e.to_string(),
+        });
+        return e;
+    }
+
+    fn reset_to_upstream(&mut self) -> Result<()> {
+        let paths = self.changed_paths.iter().cloned().collect();
+        self.report(TcrEvent::Reverting { paths });
+        let remote = self.remote.as_mut().unwrap();
+        match remote.reset_to_upstream() {
+            Ok(()) => {
+                self.reverted = true;
+                self.report(TcrEvent::ResetToUpstream);
+                Ok(())
+            }
+            Err(e) => Err(self.revert_failed(e)),
+        }
+    }
+
+    fn revert_production_code(&mut self) -> Result<()> {
+        let test_paths = self.test_paths.as_ref().unwrap();
+        let (tests, production): (Vec<PathBuf>, Vec<PathBuf>) = self
+            .changed_paths
+            .iter()
+            .cloned()
+            .partition(|p| test_paths.is_test(p));
+        self.report(TcrEvent::Reverting {
+            paths: production.clone(),
+        });
+
+        let path_reverter = self.path_reverter.as_mut().unwrap();
+        match path_reverter.revert_paths(&production) {
+            Ok(()) => {
+                self.reverted = true;
+                self.report(TcrEvent::Reverted { paths: production });
+                self.report(TcrEvent::TestsKept {
+                    paths: tests.clone(),
+                });
+                self.changed_paths = tests.into_iter().collect();
+                Ok(())
+            }
+            Err(e) => Err(self.revert_failed(e)),
+        }
+    }
+
+    fn run_revert(&mut self) -> Result<()> {
+        let paths: Vec<PathBuf> = self.changed_paths.iter().cloned().collect();
+        self.report(TcrEvent::Reverting {
+            paths: paths.clone(),
+        });
+        let revert_res = match &mut self.path_reverter {
+            Some(path_reverter) => path_reverter.revert_paths(&paths).map_err(TcrError::Io),
+            None => {
+                let revert = self.run_phase(Phase::Revert);
+                match TcrError::from_output(Phase::Revert, &revert) {
+                    Some(e) => Err(e),
+                    None => Ok(()),
+                }
+            }
+        };
         match revert_res {
-            Ok(out) => Ok(out),
-            Err(e) => {
-                println!("Error reverting: {:?}", e);
-                Err(e)
+            Ok(()) => {
+                self.reverted = true;
+                self.changed_paths.clear();
+                self.report(TcrEvent::Reverted { paths });
+                Ok(())
             }
+            Err(e) => Err(self.revert_failed(e)),
         }
     }
 }
@@ -206,6 +680,7 @@ impl Orchestrator<'_> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::log::Level;
 
     fn root() -> std::path::PathBuf {
         return std::path::PathBuf::from(r"/home/stuff");
@@ -256,8 +731,8 @@ mod tests {
         return succeed();
     }
 
-    fn logger() -> VerboseLogger {
-        return VerboseLogger::new(false);
+    fn logger() -> Logger {
+        return Logger::new(Level::Info);
     }
 
     #[test]
@@ -274,9 +749,21 @@ mod tests {
             test: &mut test,
             commit: &mut commit,
             revert: &mut revert,
-            logger: &logger(),
+            path_reverter: None,
+            remote: None,
+            working_tree: None,
+            history: None,
+            failure_policy: FailurePolicy::Revert,
+            test_paths: None,
+            test_report: None,
+            cancel: None,
+            reporters: vec![],
             last_run: None,
             delay: Duration::from_secs(0),
+            changed_paths: BTreeSet::new(),
+            stats: Stats::default(),
+            phases: vec![],
+            reverted: false,
         };
 
         orc.handle_event(ok_event()).expect("This shouldn't error");
@@ -296,9 +783,21 @@ mod tests {
             test: &mut test,
             commit: &mut commit,
             revert: &mut revert,
-            logger: &logger(),
+            path_reverter: None,
+            remote: None,
+            working_tree: None,
+            history: None,
+            failure_policy: FailurePolicy::Revert,
+            test_paths: None,
+            test_report: None,
+            cancel: None,
+            reporters: vec![],
             last_run: None,
             delay: Duration::from_secs(0),
+            changed_paths: BTreeSet::new(),
+            stats: Stats::default(),
+            phases: vec![],
+            reverted: false,
         };
 
         orc.handle_event(ok_event()).expect("This shouldn't error");
@@ -317,9 +816,21 @@ mod tests {
             test: &mut test,
             commit: &mut commit,
             revert: &mut revert,
-            logger: &logger(),
+            path_reverter: None,
+            remote: None,
+            working_tree: None,
+            history: None,
+            failure_policy: FailurePolicy::Revert,
+            test_paths: None,
+            test_report: None,
+            cancel: None,
+            reporters: vec![],
             last_run: None,
             delay: Duration::from_secs(0),
+            changed_paths: BTreeSet::new(),
+            stats: Stats::default(),
+            phases: vec![],
+            reverted: false,
         };
 
         let event = FileChangeEvent {
@@ -343,9 +854,21 @@ mod tests {
             test: &mut test,
             commit: &mut commit,
             revert: &mut revert,
-            logger: &logger(),
+            path_reverter: None,
+            remote: None,
+            working_tree: None,
+            history: None,
+            failure_policy: FailurePolicy::Revert,
+            test_paths: None,
+            test_report: None,
+            cancel: None,
+            reporters: vec![],
             last_run: None,
             delay: Duration::from_secs(10),
+            changed_paths: BTreeSet::new(),
+            stats: Stats::default(),
+            phases: vec![],
+            reverted: false,
         };
 
         let event = FileChangeEvent {
@@ -369,4 +892,644 @@ mod tests {
         };
         orc.handle_event(event).expect("This shouldn't error");
     }
+
+    #[test]
+    fn test_failure_reverts_only_changed_paths() {
+        let mut build = succeed();
+        let mut test = fail();
+        let mut commit = not_called();
+        let mut revert = not_called();
+        let mut path_reverter = MockPathReverter::default();
+        path_reverter
+            .expect_revert_paths()
+            .withf(|paths: &[PathBuf]| {
+                paths
+                    == [
+                        PathBuf::from(r"/home/stuff/a"),
+                        PathBuf::from(r"/home/stuff/b"),
+                    ]
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let logger = logger();
+        let mut orc = Orchestrator::new(
+            Checker::new(root(), None),
+            &mut build,
+            &mut test,
+            &mut commit,
+            &mut revert,
+            &logger,
+        );
+        orc.delay = Duration::from_secs(10);
+        orc.set_path_reverter(&mut path_reverter);
+
+        let event = FileChangeEvent {
+            paths: vec![
+                PathBuf::from(r"/home/stuff/b"),
+                PathBuf::from(r"/home/stuff/.git/index"),
+            ],
+            is_dir: false,
+        };
+        orc.last_run = Some(Instant::now());
+        orc.handle_event(event).expect("This shouldn't error");
+        orc.last_run = None;
+        orc.handle_event(FileChangeEvent {
+            paths: vec![PathBuf::from(r"/home/stuff/a")],
+            is_dir: false,
+        })
+        .expect("This shouldn't error");
+
+        assert!(orc.changed_paths.is_empty());
+    }
+
+    #[test]
+    fn recorded_events_are_reverted() {
+        let mut build = fail();
+        let mut test = not_called();
+        let mut commit = not_called();
+        let mut revert = not_called();
+        let mut path_reverter = MockPathReverter::default();
+        path_reverter
+            .expect_revert_paths()
+            .withf(|paths: &[PathBuf]| {
+                paths
+                    == [
+                        PathBuf::from(r"/home/stuff/a"),
+                        PathBuf::from(r"/home/stuff/b"),
+                    ]
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let logger = logger();
+        let mut orc = Orchestrator::new(
+            Checker::new(root(), None),
+            &mut build,
+            &mut test,
+            &mut commit,
+            &mut revert,
+            &logger,
+        );
+        orc.delay = Duration::from_secs(0);
+        orc.set_path_reverter(&mut path_reverter);
+
+        orc.record_events(&[FileChangeEvent {
+            paths: vec![PathBuf::from(r"/home/stuff/a")],
+            is_dir: false,
+        }]);
+        orc.handle_event(FileChangeEvent {
+            paths: vec![PathBuf::from(r"/home/stuff/b")],
+            is_dir: false,
+        })
+        .expect("This shouldn't error");
+    }
+
+    #[test]
+    fn build_that_cannot_run_is_not_reverted() {
+        let mut build = MockRunner::default();
+        build
+            .expect_run()
+            .times(1)
+            .returning(|| std::process::Command::new("not-a-real-command").output());
+        let mut test = not_called();
+        let mut commit = not_called();
+        let mut revert = not_called();
+
+        let logger = logger();
+        let mut orc = Orchestrator::new(
+            Checker::new(root(), None),
+            &mut build,
+            &mut test,
+            &mut commit,
+            &mut revert,
+            &logger,
+        );
+        match orc.run_once(vec![]) {
+            Err(TcrError::SpawnFailed { phase, .. }) => assert_eq!(phase, Phase::Build),
+            _ => panic!("expected the build to fail to start"),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn killed_tests_are_reverted() {
+        let mut build = succeed();
+        let mut test = MockRunner::default();
+        test.expect_run().times(1).returning(|| {
+            std::process::Command::new("sh")
+                .args(["-c", "kill -9 $$"])
+                .output()
+        });
+        let mut commit = not_called();
+        let mut revert = called_once();
+
+        let logger = logger();
+        let mut orc = Orchestrator::new(
+            Checker::new(root(), None),
+            &mut build,
+            &mut test,
+            &mut commit,
+            &mut revert,
+            &logger,
+        );
+        let result = orc.run_once(vec![]);
+        assert_eq!(result.unwrap(), CycleResult::TestFailed);
+    }
+
+    #[test]
+    fn timed_out_tests_are_reverted() {
+        let mut build = succeed();
+        let mut test = MockRunner::default();
+        test.expect_run()
+            .times(1)
+            .returning(|| Err(io::Error::new(io::ErrorKind::TimedOut, "timed out")));
+        let mut commit = not_called();
+        let mut revert = called_once();
+
+        let logger = logger();
+        let mut orc = Orchestrator::new(
+            Checker::new(root(), None),
+            &mut build,
+            &mut test,
+            &mut commit,
+            &mut revert,
+            &logger,
+        );
+        let result = orc.run_once(vec![]);
+        assert_eq!(result.unwrap(), CycleResult::TestFailed);
+    }
+
+    #[test]
+    fn nothing_to_commit_is_not_an_error() {
+        let mut build = succeed();
+        let mut test = succeed();
+        let mut commit = MockRunner::default();
+        // Whatever language git speaks
+        commit.expect_run().times(1).returning(|| {
+            std::process::Command::new("sh")
+                .args(["-c", "echo 'nichts zu committen'; exit 1"])
+                .output()
+        });
+        let mut revert = not_called();
+        let mut working_tree = MockWorkingTree::default();
+        working_tree
+            .expect_has_changes()
+            .times(1)
+            .returning(|| Ok(false));
+
+        let logger = logger();
+        let mut orc = Orchestrator::new(
+            Checker::new(root(), None),
+            &mut build,
+            &mut test,
+            &mut commit,
+            &mut revert,
+            &logger,
+        );
+        orc.set_working_tree(&mut working_tree);
+        let result = orc.run_once(vec![root().join("a")]);
+        assert_eq!(result.unwrap(), CycleResult::NothingToCommit);
+        assert!(orc.changed_paths.is_empty());
+        assert_eq!(orc.stats().summary().commits, 0);
+    }
+
+    #[test]
+    fn failed_commit_with_changes_is_an_error() {
+        let mut build = succeed();
+        let mut test = succeed();
+        let mut commit = fail();
+        let mut revert = not_called();
+        let mut working_tree = MockWorkingTree::default();
+        working_tree
+            .expect_has_changes()
+            .times(1)
+            .returning(|| Ok(true));
+
+        let logger = logger();
+        let mut orc = Orchestrator::new(
+            Checker::new(root(), None),
+            &mut build,
+            &mut test,
+            &mut commit,
+            &mut revert,
+            &logger,
+        );
+        orc.set_working_tree(&mut working_tree);
+        let result = orc.run_once(vec![root().join("a")]);
+        assert!(matches!(result, Err(TcrError::CommitFailed { .. })));
+        assert_eq!(orc.changed_paths.len(), 1);
+    }
+
+    #[test]
+    fn stale_test_report_is_removed() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let report = tmp_dir.path().join("report.xml");
+        std::fs::write(&report, "stale").unwrap();
+
+        let mut build = succeed();
+        let mut test = MockRunner::default();
+        let checked = report.clone();
+        test.expect_run().times(1).returning(move || {
+            assert!(!checked.exists());
+            std::process::Command::new("true").output()
+        });
+        let mut commit = called_once();
+        let mut revert = not_called();
+
+        let logger = logger();
+        let mut orc = Orchestrator::new(
+            Checker::new(root(), None),
+            &mut build,
+            &mut test,
+            &mut commit,
+            &mut revert,
+            &logger,
+        );
+        orc.set_test_report(report);
+        orc.run_once(vec![]).expect("This shouldn't error");
+    }
+
+    #[test]
+    fn cancelled_cycle_keeps_changes() {
+        let mut build = succeed();
+        let mut test = MockRunner::default();
+        test.expect_run()
+            .times(1)
+            .returning(|| Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled")));
+        let mut commit = not_called();
+        let mut revert = not_called();
+
+        let logger = logger();
+        let mut orc = Orchestrator::new(
+            Checker::new(root(), None),
+            &mut build,
+            &mut test,
+            &mut commit,
+            &mut revert,
+            &logger,
+        );
+        orc.delay = Duration::from_secs(10);
+
+        let result = orc.handle_events(vec![
+            ok_event(),
+            FileChangeEvent {
+                paths: vec![PathBuf::from(r"/home/stuff/.git/index")],
+                is_dir: false,
+            },
+        ]);
+        assert!(matches!(
+            result,
+            Err(TcrError::Cancelled { phase: Phase::Test })
+        ));
+        assert_eq!(orc.changed_paths.len(), 1);
+        // The next change shouldn't be debounced
+        assert!(orc.last_run.is_none());
+        assert_eq!(orc.stats().summary().cycles, 0);
+    }
+
+    // Records whether each run would be cancelled
+    fn cancellable(
+        cancel: &Cancel,
+        log: &std::sync::Arc<std::sync::Mutex<Vec<bool>>>,
+    ) -> MockRunner {
+        let (cancel, log) = (cancel.clone(), log.clone());
+        let mut runner = MockRunner::default();
+        runner.expect_run().returning(move || {
+            log.lock().unwrap().push(cancel.is_cancelled());
+            return std::process::Command::new("true").output();
+        });
+        return runner;
+    }
+
+    #[test]
+    fn only_build_and_tests_are_cancelled() {
+        let cancel = Cancel::default();
+        let log = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let mut build = cancellable(&cancel, &log);
+        let mut test = cancellable(&cancel, &log);
+        let mut commit = cancellable(&cancel, &log);
+        let mut revert = not_called();
+        let mut remote = remote(SyncResult::Rebased, true, false);
+
+        let logger = logger();
+        let mut orc = Orchestrator::new(
+            Checker::new(root(), None),
+            &mut build,
+            &mut test,
+            &mut commit,
+            &mut revert,
+            &logger,
+        );
+        orc.set_remote(&mut remote);
+        orc.set_cancel(cancel.clone());
+        cancel.stop();
+        let _ = orc.run_once(vec![root().join("a")]);
+        // The build and tests, then the commit and the tests after rebasing
+        assert_eq!(*log.lock().unwrap(), [true, true, false, false]);
+    }
+
+    fn remote(pull: SyncResult, push: bool, reset: bool) -> MockRemote {
+        let mut remote = MockRemote::default();
+        remote
+            .expect_pull_rebase()
+            .times(1)
+            .return_once(move || Ok(pull));
+        remote
+            .expect_push()
+            .times(if push { 1 } else { 0 })
+            .returning(|| Ok(()));
+        remote
+            .expect_reset_to_upstream()
+            .times(if reset { 1 } else { 0 })
+            .returning(|| Ok(()));
+        return remote;
+    }
+
+    fn run_limbo(test: &mut MockRunner, remote: &mut MockRemote) {
+        let mut build = succeed();
+        let mut commit = called_once();
+        let mut revert = not_called();
+
+        let logger = logger();
+        let mut orc = Orchestrator::new(
+            Checker::new(root(), None),
+            &mut build,
+            test,
+            &mut commit,
+            &mut revert,
+            &logger,
+        );
+        orc.delay = Duration::from_secs(0);
+        orc.set_remote(remote);
+
+        orc.handle_event(ok_event()).expect("This shouldn't error");
+    }
+
+    #[test]
+    fn limbo_up_to_date_pushes() {
+        let mut test = called_once();
+        let mut remote = remote(SyncResult::UpToDate, true, false);
+        run_limbo(&mut test, &mut remote);
+    }
+
+    #[test]
+    fn limbo_rebased_tests_again_and_pushes() {
+        let mut test = MockRunner::default();
+        test.expect_run()
+            .times(2)
+            .returning(|| std::process::Command::new("true").output());
+        let mut remote = remote(SyncResult::Rebased, true, false);
+        run_limbo(&mut test, &mut remote);
+    }
+
+    #[test]
+    fn limbo_rebased_test_fails_resets() {
+        let mut test = MockRunner::default();
+        let mut seq = mockall::Sequence::new();
+        test.expect_run()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|| std::process::Command::new("true").output());
+        test.expect_run()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|| std::process::Command::new("false").output());
+        let mut remote = remote(SyncResult::Rebased, false, true);
+        run_limbo(&mut test, &mut remote);
+    }
+
+    #[test]
+    fn limbo_conflict_resets() {
+        let mut test = called_once();
+        let mut remote = remote(SyncResult::Conflict, false, true);
+        run_limbo(&mut test, &mut remote);
+    }
+
+    fn test_paths() -> TestPaths {
+        return TestPaths::new(root(), &[String::from("tests/**")]).unwrap();
+    }
+
+    fn run_relaxed(path: &str, revert: &mut MockRunner) -> Option<CycleResult> {
+        let mut build = succeed();
+        let mut test = fail();
+        let mut commit = not_called();
+
+        let logger = logger();
+        let mut orc = Orchestrator::new(
+            Checker::new(root(), None),
+            &mut build,
+            &mut test,
+            &mut commit,
+            revert,
+            &logger,
+        );
+        orc.delay = Duration::from_secs(0);
+        orc.set_failure_policy(FailurePolicy::Relaxed, test_paths());
+
+        let event = FileChangeEvent {
+            paths: vec![root().join(path)],
+            is_dir: false,
+        };
+        return orc.handle_event(event).expect("This shouldn't error");
+    }
+
+    #[test]
+    fn relaxed_failing_test_only_change_is_kept() {
+        let mut revert = not_called();
+        assert_eq!(
+            run_relaxed("tests/new_test.rs", &mut revert),
+            Some(CycleResult::FailingTestKept)
+        );
+    }
+
+    #[test]
+    fn relaxed_failing_production_change_is_reverted() {
+        let mut revert = called_once();
+        assert_eq!(
+            run_relaxed("src/lib.rs", &mut revert),
+            Some(CycleResult::TestFailed)
+        );
+    }
+
+    #[test]
+    fn keep_tests_reverts_only_production_code() {
+        let mut build = succeed();
+        let mut test = fail();
+        let mut commit = not_called();
+        let mut revert = not_called();
+        let mut path_reverter = MockPathReverter::default();
+        path_reverter
+            .expect_revert_paths()
+            .withf(|paths: &[PathBuf]| paths == [PathBuf::from(r"/home/stuff/src/lib.rs")])
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let logger = logger();
+        let mut orc = Orchestrator::new(
+            Checker::new(root(), None),
+            &mut build,
+            &mut test,
+            &mut commit,
+            &mut revert,
+            &logger,
+        );
+        orc.delay = Duration::from_secs(0);
+        orc.set_path_reverter(&mut path_reverter);
+        orc.set_failure_policy(FailurePolicy::KeepTests, test_paths());
+
+        let event = FileChangeEvent {
+            paths: vec![root().join("src/lib.rs"), root().join("tests/it.rs")],
+            is_dir: false,
+        };
+        orc.handle_event(event).expect("This shouldn't error");
+
+        assert_eq!(
+            orc.changed_paths.iter().collect::<Vec<_>>(),
+            vec![&root().join("tests/it.rs")]
+        );
+    }
+
+    #[test]
+    fn forced_commits_are_counted() {
+        let mut build = not_called();
+        let mut test = not_called();
+        let mut commit = called_once();
+        let mut revert = not_called();
+
+        let logger = logger();
+        let mut orc = Orchestrator::new(
+            Checker::new(root(), None),
+            &mut build,
+            &mut test,
+            &mut commit,
+            &mut revert,
+            &logger,
+        );
+        assert_eq!(orc.force_commit().unwrap(), CycleResult::Committed);
+        let summary = orc.stats().summary();
+        assert_eq!(summary.commits, 1);
+        assert_eq!(summary.cycles, 0);
+    }
+
+    #[test]
+    fn stats_are_recorded() {
+        let mut build = succeed();
+        let mut test = fail();
+        let mut commit = not_called();
+        let mut revert = called_once();
+
+        let logger = logger();
+        let mut orc = Orchestrator::new(
+            Checker::new(root(), None),
+            &mut build,
+            &mut test,
+            &mut commit,
+            &mut revert,
+            &logger,
+        );
+        orc.delay = Duration::from_secs(0);
+        orc.handle_event(ok_event()).expect("This shouldn't error");
+
+        let summary = orc.stats().summary();
+        assert_eq!(summary.cycles, 1);
+        assert_eq!(summary.commits, 0);
+        assert_eq!(summary.test_reverts, 1);
+        assert!(summary.avg_build_ms.is_some());
+    }
+
+    #[test]
+    fn cycles_are_recorded() {
+        let mut build = succeed();
+        let mut test = fail();
+        let mut commit = not_called();
+        let mut revert = called_once();
+        let mut history = MockRecorder::default();
+        history
+            .expect_record()
+            .withf(|r: &CycleRecord| {
+                r.result == Some(CycleResult::TestFailed)
+                    && r.reverted
+                    && r.paths == [PathBuf::from(r"/home/stuff/hi")]
+                    && r.phases.iter().map(|p| p.phase).collect::<Vec<_>>()
+                        == [Phase::Build, Phase::Test, Phase::Revert]
+                    && r.phases[1].exit_code == Some(1)
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let logger = logger();
+        let mut orc = Orchestrator::new(
+            Checker::new(root(), None),
+            &mut build,
+            &mut test,
+            &mut commit,
+            &mut revert,
+            &logger,
+        );
+        orc.delay = Duration::from_secs(0);
+        orc.set_history(&mut history);
+        orc.handle_event(ok_event()).expect("This shouldn't error");
+    }
+
+    struct RecordingReporter {
+        events: std::rc::Rc<std::cell::RefCell<Vec<TcrEvent>>>,
+    }
+
+    impl Reporter for RecordingReporter {
+        fn report(&mut self, event: &TcrEvent) {
+            self.events.borrow_mut().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn events_are_reported() {
+        let mut build = succeed();
+        let mut test = succeed();
+        let mut commit = called_once();
+        let mut revert = not_called();
+        let events = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+
+        let logger = logger();
+        let mut orc = Orchestrator::new(
+            Checker::new(root(), None),
+            &mut build,
+            &mut test,
+            &mut commit,
+            &mut revert,
+            &logger,
+        );
+        orc.set_reporter(Box::new(RecordingReporter {
+            events: events.clone(),
+        }));
+        orc.handle_event(ok_event()).expect("This shouldn't error");
+        orc.handle_event(ok_event()).expect("This shouldn't error");
+
+        let names: Vec<String> = events
+            .borrow()
+            .iter()
+            .map(|e| {
+                serde_json::to_value(e).unwrap()["event"]
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "change_seen",
+                "cycle_started",
+                "phase_started",
+                "phase_finished",
+                "phase_started",
+                "phase_finished",
+                "phase_started",
+                "phase_finished",
+                "committed",
+                "cycle_finished",
+                "debounced",
+            ]
+        );
+    }
 }
@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::executor::Cancel;
+
+// A phase's command, or several independent ones to run at the same time
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Commands {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Commands {
+    pub fn into_vec(self) -> Vec<String> {
+        return match self {
+            Commands::One(cmd) => vec![cmd],
+            Commands::Many(cmds) => cmds,
+        };
+    }
+}
+
+// How often we check on the commands
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+struct Running {
+    child: Child,
+    stdout: JoinHandle<Vec<u8>>,
+    stderr: JoinHandle<Vec<u8>>,
+    status: Option<ExitStatus>,
+}
+
+fn read_all(stream: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    return std::thread::spawn(move || {
+        let mut buf = vec![];
+        if let Some(mut stream) = stream {
+            let _ = stream.read_to_end(&mut buf);
+        }
+        return buf;
+    });
+}
+
+// Runs `cmds` at the same time and waits for all of them. As soon as one
+// fails the rest are killed. The output is each command's output in turn,
+// with the status of the first to fail, or success. If `cancel` is
+// cancelled they're all killed and it's an Interrupted error, and if they
+// take longer than `timeout` it's a TimedOut one.
+pub fn output_all(
+    cmds: &mut [Command],
+    cancel: Option<&Cancel>,
+    timeout: Option<Duration>,
+) -> io::Result<Output> {
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let mut running = vec![];
+    for cmd in cmds.iter_mut() {
+        // In a group of their own, so anything they start can be killed too
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(cmd, 0);
+        let spawned = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                kill_all(&mut running);
+                return Err(e);
+            }
+        };
+        running.push(Running {
+            stdout: read_all(child.stdout.take()),
+            stderr: read_all(child.stderr.take()),
+            child,
+            status: None,
+        });
+    }
+
+    let mut failed: Option<ExitStatus> = None;
+    while failed.is_none() && running.iter().any(|r| r.status.is_none()) {
+        if cancel.is_some_and(Cancel::is_cancelled) {
+            kill_all(&mut running);
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"));
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            kill_all(&mut running);
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "timed out"));
+        }
+        let mut error: Option<io::Error> = None;
+        for r in running.iter_mut().filter(|r| r.status.is_none()) {
+            match r.child.try_wait() {
+                Ok(status) => r.status = status,
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            }
+            match r.status {
+                Some(status) if !status.success() => {
+                    failed = Some(status);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        // Not knowing how one's doing, we can't wait for the rest
+        if let Some(e) = error {
+            kill_all(&mut running);
+            return Err(e);
+        }
+        if failed.is_none() {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+    kill_all(&mut running);
+
+    let mut output = Output {
+        status: failed.unwrap_or_else(|| running[0].status.unwrap()),
+        stdout: vec![],
+        stderr: vec![],
+    };
+    for r in running {
+        output.stdout.extend(r.stdout.join().unwrap_or_default());
+        output.stderr.extend(r.stderr.join().unwrap_or_default());
+    }
+    return Ok(output);
+}
+
+fn kill_all(running: &mut [Running]) {
+    for r in running.iter_mut().filter(|r| r.status.is_none()) {
+        #[cfg(unix)]
+        unsafe {
+            libc::kill(-(r.child.id() as i32), libc::SIGKILL);
+        }
+        #[cfg(not(unix))]
+        let _ = r.child.kill();
+        r.status = r.child.wait().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn sh(script: &str) -> Command {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", script]);
+        return cmd;
+    }
+
+    #[test]
+    fn runs_them_all() {
+        let output = output_all(&mut [sh("echo a"), sh("echo b; echo c >&2")], None, None).unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "a\nb\n");
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "c\n");
+    }
+
+    #[test]
+    fn first_failure_kills_the_rest() {
+        let start = Instant::now();
+        let output = output_all(
+            &mut [sh("sleep 10; echo too late"), sh("echo oops; exit 3")],
+            None,
+            None,
+        )
+        .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "oops\n");
+    }
+
+    #[test]
+    fn cancelling_kills_them_all() {
+        let cancel = Cancel::default();
+        cancel.arm();
+        let cancel_later = cancel.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            cancel_later.cancel();
+        });
+        let start = Instant::now();
+        let err =
+            output_all(&mut [sh("sleep 10"), sh("sleep 10")], Some(&cancel), None).unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+    }
+
+    #[test]
+    fn timing_out_kills_them_all() {
+        let start = Instant::now();
+        let timeout = Some(Duration::from_millis(100));
+        let err = output_all(&mut [sh("sleep 10"), sh("true")], None, timeout).unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn test_commands_config() {
+        let one: Commands = serde_json::from_str(r#""cargo build""#).unwrap();
+        assert_eq!(one.into_vec(), ["cargo build"]);
+        let many: Commands = serde_json::from_str(r#"["cargo clippy", "cargo build"]"#).unwrap();
+        assert_eq!(many.into_vec(), ["cargo clippy", "cargo build"]);
+    }
+}
@@ -0,0 +1,187 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+
+use crate::events::{self, Reporter, TcrEvent};
+use crate::status::Tracker;
+
+// A small HTTP server for status pages and editor integrations:
+//   GET /status  the current state as JSON
+//   GET /events  a stream of events as server-sent events
+#[derive(Clone)]
+pub struct Server {
+    pub addr: SocketAddr,
+    tracker: Tracker,
+    // Web pages from this origin may read the responses. Nothing else can,
+    // as the events include the build and test output.
+    allow_origin: Option<String>,
+    // One per connected /events client
+    subscribers: Arc<Mutex<Vec<Sender<String>>>>,
+}
+
+impl Server {
+    pub fn start<A: ToSocketAddrs>(
+        addr: A,
+        tracker: Tracker,
+        allow_origin: Option<String>,
+    ) -> io::Result<Server> {
+        let listener = TcpListener::bind(addr)?;
+        let server = Server {
+            addr: listener.local_addr()?,
+            tracker,
+            allow_origin,
+            subscribers: Arc::new(Mutex::new(vec![])),
+        };
+        let accepting = server.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let connection = accepting.clone();
+                std::thread::spawn(move || {
+                    let _ = connection.handle(stream);
+                });
+            }
+        });
+        return Ok(server);
+    }
+
+    fn handle(&self, stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // Skip the headers, we don't need any of them
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or("");
+        let path = parts.next().unwrap_or("");
+        return match (method, path) {
+            ("GET", "/status") => {
+                let body = serde_json::to_string(&self.tracker.status())?;
+                self.respond(stream, "200 OK", "application/json", &body)
+            }
+            ("GET", "/events") => self.stream_events(stream),
+            ("GET", _) => self.respond(stream, "404 Not Found", "text/plain", "Not found\n"),
+            _ => self.respond(
+                stream,
+                "405 Method Not Allowed",
+                "text/plain",
+                "Method not allowed\n",
+            ),
+        };
+    }
+
+    fn stream_events(&self, mut stream: TcpStream) -> io::Result<()> {
+        let (tx, rx) = channel();
+        self.subscribers.lock().unwrap().push(tx);
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\n\
+             Content-Type: text/event-stream\r\n\
+             Cache-Control: no-cache\r\n\
+             {}\r\n",
+            self.cors()
+        )?;
+        stream.flush()?;
+        // Ends when the client goes away, which drops rx so the reporter
+        // forgets about us on the next event
+        for json in rx {
+            write!(stream, "data: {}\n\n", json)?;
+            stream.flush()?;
+        }
+        return Ok(());
+    }
+
+    fn cors(&self) -> String {
+        return match &self.allow_origin {
+            Some(origin) => format!("Access-Control-Allow-Origin: {}\r\n", origin),
+            None => String::new(),
+        };
+    }
+
+    fn respond(
+        &self,
+        mut stream: TcpStream,
+        status: &str,
+        content_type: &str,
+        body: &str,
+    ) -> io::Result<()> {
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\n\
+             Content-Type: {}\r\n\
+             Content-Length: {}\r\n\
+             {}\
+             Connection: close\r\n\r\n{}",
+            status,
+            content_type,
+            body.len(),
+            self.cors(),
+            body
+        )?;
+        return stream.flush();
+    }
+}
+
+impl Reporter for Server {
+    fn report(&mut self, event: &TcrEvent) {
+        let json = events::to_json(event);
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(json.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn get(server_addr: &str, path: &str) -> String {
+        let mut stream = TcpStream::connect(server_addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        return response;
+    }
+
+    #[test]
+    fn serves_status_and_events() {
+        let mut server = Server::start("127.0.0.1:0", Tracker::default(), None).unwrap();
+        let addr = server.addr.to_string();
+
+        let response = get(&addr, "/status");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(!response.contains("Access-Control-Allow-Origin"));
+        assert!(response.contains(r#""state":"idle""#));
+        assert!(get(&addr, "/nope").starts_with("HTTP/1.1 404"));
+
+        let mut stream = TcpStream::connect(&addr).unwrap();
+        write!(stream, "GET /events HTTP/1.1\r\n\r\n").unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while line.trim() != "" || line.is_empty() {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+        server.report(&TcrEvent::Committed);
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with("data: {"));
+        assert!(line.contains(r#""event":"committed""#));
+    }
+
+    #[test]
+    fn cross_origin_is_opt_in() {
+        let origin = Some(String::from("http://localhost:8000"));
+        let server = Server::start("127.0.0.1:0", Tracker::default(), origin).unwrap();
+        let response = get(&server.addr.to_string(), "/status");
+        assert!(response.contains("Access-Control-Allow-Origin: http://localhost:8000\r\n"));
+    }
+}
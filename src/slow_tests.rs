@@ -0,0 +1,227 @@
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::{channel, Sender};
+use std::time::{Duration, Instant};
+
+use crate::events::{Reporter, TcrEvent};
+use crate::git;
+use crate::orchestrator::CycleResult;
+use crate::test_results;
+
+// What to do when the slow tests fail
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SlowTestPolicy {
+    // Let you know and mark the commit in the history
+    Notify,
+    // Revert the commit as well, unless there are newer ones
+    Revert,
+}
+
+impl Default for SlowTestPolicy {
+    fn default() -> Self {
+        return SlowTestPolicy::Notify;
+    }
+}
+
+// How a run of the slow tests went
+pub struct Finished {
+    pub commit: String,
+    pub output: io::Result<std::process::Output>,
+    pub duration: Duration,
+}
+
+impl Finished {
+    pub fn success(&self) -> bool {
+        return matches!(&self.output, Ok(out) if out.status.success());
+    }
+
+    pub fn event(&self, reverted: bool) -> TcrEvent {
+        let (exit_code, stdout, stderr, error) = match &self.output {
+            Ok(out) => (
+                out.status.code(),
+                String::from_utf8_lossy(&out.stdout).to_string(),
+                String::from_utf8_lossy(&out.stderr).to_string(),
+                None,
+            ),
+            Err(e) => (None, String::new(), String::new(), Some(e.to_string())),
+        };
+        let tests = test_results::parse(&format!("{}\n{}", stdout, stderr));
+        return TcrEvent::SlowTestsFinished {
+            commit: self.commit.clone(),
+            success: self.success(),
+            exit_code,
+            duration_ms: self.duration.as_millis() as u64,
+            stdout,
+            stderr,
+            error,
+            tests,
+            reverted,
+        };
+    }
+}
+
+// Runs slow_test_cmd in the background after every commit, in a worktree
+// of its own so it isn't thrown by changes made in the meantime. Commits
+// made while it's running are tested together once it's done.
+pub struct SlowTests {
+    root: PathBuf,
+    commits: Sender<String>,
+    // A cycle's commit isn't final until the cycle finishes, as limbo mode
+    // rebases it onto the upstream branch
+    in_cycle: bool,
+    committed: bool,
+}
+
+impl SlowTests {
+    // `cmd` runs in `worktree`. Results are sent down `tx`, wrapped by `f`.
+    pub fn start<T: Send + 'static>(
+        root: &Path,
+        worktree: PathBuf,
+        mut cmd: Command,
+        tx: Sender<T>,
+        f: fn(Finished) -> T,
+    ) -> SlowTests {
+        let (commits, rx) = channel::<String>();
+        let repo = root.to_path_buf();
+        std::thread::spawn(move || {
+            while let Ok(mut commit) = rx.recv() {
+                // Only the latest commit needs testing
+                while let Ok(newer) = rx.try_recv() {
+                    commit = newer;
+                }
+                let start = Instant::now();
+                let output = git::checkout_worktree(&repo, &worktree, &commit)
+                    .and_then(|()| cmd.current_dir(&worktree).output());
+                let finished = Finished {
+                    commit,
+                    output,
+                    duration: start.elapsed(),
+                };
+                if tx.send(f(finished)).is_err() {
+                    return;
+                }
+            }
+        });
+        return SlowTests {
+            root: root.to_path_buf(),
+            commits,
+            in_cycle: false,
+            committed: false,
+        };
+    }
+
+    fn test_head(&mut self) {
+        self.committed = false;
+        if let Ok(head) = git::head(&self.root) {
+            let _ = self.commits.send(head);
+        }
+    }
+}
+
+impl Reporter for SlowTests {
+    fn report(&mut self, event: &TcrEvent) {
+        match event {
+            TcrEvent::CycleStarted { .. } => {
+                // The last cycle committed but ended in an error
+                if self.committed {
+                    self.test_head();
+                }
+                self.in_cycle = true;
+            }
+            // Made with `c`, outside of a cycle
+            TcrEvent::Committed if !self.in_cycle => self.test_head(),
+            TcrEvent::Committed => self.committed = true,
+            TcrEvent::CycleFinished { result, .. } => {
+                if *result == CycleResult::Committed && self.committed {
+                    self.test_head();
+                }
+                self.in_cycle = false;
+                self.committed = false;
+            }
+            TcrEvent::Cancelled { .. } => self.in_cycle = false,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_git(root: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(args)
+            .current_dir(root)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn runs_against_the_commit() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let root = tmp_dir.path().join("repo");
+        std::fs::create_dir(&root).unwrap();
+        run_git(&root, &["init", "-q"]);
+        run_git(&root, &["config", "user.name", "tcr"]);
+        run_git(&root, &["config", "user.email", "tcr@example.com"]);
+        std::fs::write(root.join("a"), "committed").unwrap();
+        run_git(&root, &["add", "a"]);
+        run_git(&root, &["commit", "-q", "-m", "a"]);
+        // Not committed, so the slow tests shouldn't see it
+        std::fs::write(root.join("a"), "in progress").unwrap();
+
+        let (tx, rx) = channel();
+        let mut cmd = Command::new("cat");
+        cmd.arg("a");
+        let mut slow = SlowTests::start(&root, tmp_dir.path().join("slow"), cmd, tx, |f| f);
+        slow.report(&TcrEvent::CycleStarted { paths: vec![] });
+        slow.report(&TcrEvent::Committed);
+        // Limbo mode rebases the commit before the cycle finishes
+        std::fs::write(root.join("b"), "upstream").unwrap();
+        run_git(&root, &["add", "b"]);
+        run_git(&root, &["commit", "-q", "-m", "b"]);
+        slow.report(&TcrEvent::CycleFinished {
+            result: CycleResult::Committed,
+            paths: vec![],
+            reverted: false,
+        });
+
+        let finished = rx.recv().unwrap();
+        assert_eq!(finished.commit, git::head(&root).unwrap());
+        let output = finished.output.unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "committed");
+    }
+
+    #[test]
+    fn only_commits_that_are_kept_are_tested() {
+        let tmp_dir = tempdir::TempDir::new("test").unwrap();
+        let root = tmp_dir.path().join("repo");
+        std::fs::create_dir(&root).unwrap();
+        run_git(&root, &["init", "-q"]);
+        run_git(&root, &["config", "user.name", "tcr"]);
+        run_git(&root, &["config", "user.email", "tcr@example.com"]);
+        run_git(&root, &["commit", "-q", "--allow-empty", "-m", "a"]);
+
+        let (tx, rx) = channel();
+        let cmd = Command::new("true");
+        let mut slow = SlowTests::start(&root, tmp_dir.path().join("slow"), cmd, tx, |f| f);
+        // Limbo mode couldn't push it, so it's gone
+        slow.report(&TcrEvent::CycleStarted { paths: vec![] });
+        slow.report(&TcrEvent::Committed);
+        slow.report(&TcrEvent::CycleFinished {
+            result: CycleResult::SyncFailed,
+            paths: vec![],
+            reverted: true,
+        });
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+
+        // Committed with `c`
+        slow.report(&TcrEvent::Committed);
+        let finished = rx.recv().unwrap();
+        assert_eq!(finished.commit, git::head(&root).unwrap());
+    }
+}
@@ -0,0 +1,145 @@
+use serde::Serialize;
+use std::time::Duration;
+
+use crate::orchestrator::{CycleResult, Phase};
+
+// Counters for a single tcr session
+#[derive(Default, Clone, Debug)]
+pub struct Stats {
+    cycles: u32,
+    commits: u32,
+    build_reverts: u32,
+    test_reverts: u32,
+    green_streak: u32,
+    longest_green_streak: u32,
+    build_times: Vec<Duration>,
+    test_times: Vec<Duration>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Summary {
+    pub cycles: u32,
+    pub commits: u32,
+    pub build_reverts: u32,
+    pub test_reverts: u32,
+    pub avg_build_ms: Option<u128>,
+    pub avg_test_ms: Option<u128>,
+    pub longest_green_streak: u32,
+}
+
+fn average_ms(times: &[Duration]) -> Option<u128> {
+    if times.is_empty() {
+        return None;
+    }
+    let total: Duration = times.iter().sum();
+    return Some(total.as_millis() / times.len() as u128);
+}
+
+impl Stats {
+    pub fn record_cycle(&mut self, result: CycleResult) {
+        self.cycles += 1;
+        if result == CycleResult::Committed {
+            self.commits += 1;
+        }
+        // Nothing to commit is still green
+        if result == CycleResult::Committed || result == CycleResult::NothingToCommit {
+            self.green_streak += 1;
+            self.longest_green_streak = self.longest_green_streak.max(self.green_streak);
+        } else {
+            self.green_streak = 0;
+        }
+    }
+
+    // A commit made outside of a cycle, with `c`
+    pub fn record_commit(&mut self) {
+        self.commits += 1;
+    }
+
+    pub fn record_revert(&mut self, phase: Phase) {
+        match phase {
+            Phase::Build => self.build_reverts += 1,
+            Phase::Test => self.test_reverts += 1,
+            _ => {}
+        }
+    }
+
+    pub fn record_duration(&mut self, phase: Phase, duration: Duration) {
+        match phase {
+            Phase::Build => self.build_times.push(duration),
+            Phase::Test => self.test_times.push(duration),
+            _ => {}
+        }
+    }
+
+    pub fn summary(&self) -> Summary {
+        return Summary {
+            cycles: self.cycles,
+            commits: self.commits,
+            build_reverts: self.build_reverts,
+            test_reverts: self.test_reverts,
+            avg_build_ms: average_ms(&self.build_times),
+            avg_test_ms: average_ms(&self.test_times),
+            longest_green_streak: self.longest_green_streak,
+        };
+    }
+}
+
+fn format_ms(ms: Option<u128>) -> String {
+    return match ms {
+        Some(ms) => format!("{}ms", ms),
+        None => String::from("-"),
+    };
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "Cycles run: {}", self.cycles)?;
+        writeln!(f, "Commits: {}", self.commits)?;
+        writeln!(
+            f,
+            "Reverts: {} (build: {}, test: {})",
+            self.build_reverts + self.test_reverts,
+            self.build_reverts,
+            self.test_reverts
+        )?;
+        writeln!(f, "Average build: {}", format_ms(self.avg_build_ms))?;
+        writeln!(f, "Average test: {}", format_ms(self.avg_test_ms))?;
+        write!(f, "Longest green streak: {}", self.longest_green_streak)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_green_streak() {
+        let mut stats = Stats::default();
+        stats.record_cycle(CycleResult::Committed);
+        stats.record_cycle(CycleResult::Committed);
+        stats.record_cycle(CycleResult::TestFailed);
+        stats.record_cycle(CycleResult::Committed);
+
+        let summary = stats.summary();
+        assert_eq!(summary.cycles, 4);
+        assert_eq!(summary.commits, 3);
+        assert_eq!(summary.longest_green_streak, 2);
+    }
+
+    #[test]
+    fn test_averages() {
+        let mut stats = Stats::default();
+        assert_eq!(stats.summary().avg_build_ms, None);
+
+        stats.record_duration(Phase::Build, Duration::from_millis(100));
+        stats.record_duration(Phase::Build, Duration::from_millis(300));
+        stats.record_duration(Phase::Test, Duration::from_millis(50));
+        stats.record_revert(Phase::Build);
+
+        let summary = stats.summary();
+        assert_eq!(summary.avg_build_ms, Some(200));
+        assert_eq!(summary.avg_test_ms, Some(50));
+        assert_eq!(summary.build_reverts, 1);
+        assert_eq!(summary.test_reverts, 0);
+    }
+}
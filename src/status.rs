@@ -0,0 +1,164 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+
+use crate::events::{Reporter, TcrEvent};
+use crate::orchestrator::{CycleResult, Phase};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Idle,
+    Running,
+    Paused,
+}
+
+// What /status returns
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Status {
+    pub state: State,
+    // The phase currently running, if any
+    pub phase: Option<Phase>,
+    pub last_result: Option<CycleResult>,
+    pub last_cycle_at: Option<DateTime<Utc>>,
+    pub cycles: u32,
+    pub commits: u32,
+    // A commit outside of a cycle was made with `c`
+    #[serde(skip)]
+    in_cycle: bool,
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        return Status {
+            state: State::Idle,
+            phase: None,
+            last_result: None,
+            last_cycle_at: None,
+            cycles: 0,
+            commits: 0,
+            in_cycle: false,
+        };
+    }
+}
+
+impl Status {
+    fn update(&mut self, event: &TcrEvent) {
+        match event {
+            TcrEvent::CycleStarted { .. } => self.in_cycle = true,
+            TcrEvent::PhaseStarted { phase } => {
+                self.state = State::Running;
+                self.phase = Some(*phase);
+            }
+            TcrEvent::PhaseFinished { .. } => self.phase = None,
+            TcrEvent::CycleFinished { result, .. } => {
+                self.in_cycle = false;
+                self.state = State::Idle;
+                self.last_result = Some(*result);
+                self.last_cycle_at = Some(Utc::now());
+                self.cycles += 1;
+                if *result == CycleResult::Committed {
+                    self.commits += 1;
+                }
+            }
+            TcrEvent::Committed if !self.in_cycle => {
+                self.state = State::Idle;
+                self.commits += 1;
+            }
+            TcrEvent::NothingToCommit if !self.in_cycle => self.state = State::Idle,
+            TcrEvent::Cancelled { .. } => {
+                self.in_cycle = false;
+                self.state = State::Idle;
+                self.phase = None;
+            }
+            TcrEvent::Error { .. } if self.state == State::Running => {
+                self.in_cycle = false;
+                self.state = State::Idle;
+                self.phase = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    status: Status,
+    paused: bool,
+}
+
+// Keeps track of what tcr is doing for the status server and the control
+// socket. Clones share the same state.
+#[derive(Clone, Default)]
+pub struct Tracker {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Tracker {
+    pub fn set_paused(&self, paused: bool) {
+        self.shared.lock().unwrap().paused = paused;
+    }
+
+    pub fn status(&self) -> Status {
+        let shared = self.shared.lock().unwrap();
+        let mut status = shared.status.clone();
+        if shared.paused && status.state == State::Idle {
+            status.state = State::Paused;
+        }
+        return status;
+    }
+}
+
+impl Reporter for Tracker {
+    fn report(&mut self, event: &TcrEvent) {
+        self.shared.lock().unwrap().status.update(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_follows_events() {
+        let mut tracker = Tracker::default();
+        tracker.report(&TcrEvent::PhaseStarted { phase: Phase::Test });
+        assert_eq!(tracker.status().state, State::Running);
+        assert_eq!(tracker.status().phase, Some(Phase::Test));
+
+        tracker.report(&TcrEvent::CycleFinished {
+            result: CycleResult::Committed,
+            paths: vec![],
+            reverted: false,
+        });
+        tracker.set_paused(true);
+        let status = tracker.status();
+        assert_eq!(status.state, State::Paused);
+        assert_eq!(status.last_result, Some(CycleResult::Committed));
+        assert_eq!(status.commits, 1);
+    }
+
+    #[test]
+    fn commits_made_with_c_are_counted() {
+        let mut tracker = Tracker::default();
+        tracker.report(&TcrEvent::PhaseStarted {
+            phase: Phase::Commit,
+        });
+        tracker.report(&TcrEvent::Committed);
+        let status = tracker.status();
+        assert_eq!(status.state, State::Idle);
+        assert_eq!(status.commits, 1);
+        assert_eq!(status.cycles, 0);
+
+        // A cycle's commit is counted once it's finished
+        tracker.report(&TcrEvent::CycleStarted { paths: vec![] });
+        tracker.report(&TcrEvent::Committed);
+        assert_eq!(tracker.status().commits, 1);
+        tracker.report(&TcrEvent::CycleFinished {
+            result: CycleResult::Committed,
+            paths: vec![],
+            reverted: false,
+        });
+        assert_eq!(tracker.status().commits, 2);
+    }
+}
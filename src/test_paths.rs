@@ -0,0 +1,54 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::path::{Path, PathBuf};
+
+// Decides whether a changed path is a test, using the `test_paths` globs
+// from the config. Globs are matched against paths relative to the root.
+pub struct TestPaths {
+    root: PathBuf,
+    globs: GlobSet,
+}
+
+impl TestPaths {
+    pub fn new(root: PathBuf, patterns: &[String]) -> Result<TestPaths, globset::Error> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            builder.add(Glob::new(pattern)?);
+        }
+        return Ok(TestPaths {
+            root,
+            globs: builder.build()?,
+        });
+    }
+
+    pub fn is_test(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        return self.globs.is_match(relative);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_test() {
+        let root = PathBuf::from(r"/home/stuff");
+        let paths = TestPaths::new(
+            root.clone(),
+            &[String::from("tests/**"), String::from("**/*_test.go")],
+        )
+        .unwrap();
+
+        assert!(paths.is_test(&root.join("tests").join("it.rs")));
+        assert!(paths.is_test(&root.join("pkg").join("foo_test.go")));
+        assert!(!paths.is_test(&root.join("src").join("main.rs")));
+        assert!(!paths.is_test(&root.join("pkg").join("foo.go")));
+    }
+
+    #[test]
+    fn test_invalid_glob() {
+        TestPaths::new(PathBuf::from(r"/home/stuff"), &[String::from("a[")])
+            .err()
+            .expect("Expected this to fail");
+    }
+}
@@ -0,0 +1,277 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// What we could make out of a test run's output
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct TestResults {
+    pub passed: u32,
+    pub failed: u32,
+    pub ignored: u32,
+    // Names of the failing tests
+    pub failures: Vec<String>,
+}
+
+impl TestResults {
+    fn record(&mut self, name: &str, outcome: Outcome) {
+        match outcome {
+            Outcome::Passed => self.passed += 1,
+            Outcome::Failed => {
+                self.failed += 1;
+                self.failures.push(name.to_string());
+            }
+            Outcome::Ignored => self.ignored += 1,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        return self.passed == 0 && self.failed == 0 && self.ignored == 0;
+    }
+}
+
+impl std::fmt::Display for TestResults {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} passed, {} failed", self.passed, self.failed)?;
+        if self.ignored > 0 {
+            write!(f, ", {} ignored", self.ignored)?;
+        }
+        return Ok(());
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Outcome {
+    Passed,
+    Failed,
+    Ignored,
+}
+
+// Tries each format we know in turn: libtest JSON, go test -json, JUnit XML,
+// cargo test, pytest and TAP
+pub fn parse(output: &str) -> Option<TestResults> {
+    let parsers: [fn(&str) -> TestResults; 6] = [
+        parse_libtest_json,
+        parse_go_json,
+        parse_junit,
+        parse_cargo,
+        parse_pytest,
+        parse_tap,
+    ];
+    return parsers
+        .iter()
+        .map(|p| p(output))
+        .find(|results| !results.is_empty());
+}
+
+fn json_lines(output: &str) -> impl Iterator<Item = Value> + '_ {
+    return output
+        .lines()
+        .filter(|l| l.trim_start().starts_with('{'))
+        .filter_map(|l| serde_json::from_str(l).ok());
+}
+
+// cargo test -- -Z unstable-options --format json
+fn parse_libtest_json(output: &str) -> TestResults {
+    let mut results = TestResults::default();
+    for line in json_lines(output).filter(|v| v["type"] == "test") {
+        let outcome = match line["event"].as_str() {
+            Some("ok") => Outcome::Passed,
+            Some("failed") | Some("timeout") => Outcome::Failed,
+            Some("ignored") => Outcome::Ignored,
+            _ => continue,
+        };
+        results.record(line["name"].as_str().unwrap_or(""), outcome);
+    }
+    return results;
+}
+
+fn parse_go_json(output: &str) -> TestResults {
+    let mut results = TestResults::default();
+    // Lines without a Test are about the whole package
+    for line in json_lines(output).filter(|v| v["Test"].is_string()) {
+        let outcome = match line["Action"].as_str() {
+            Some("pass") => Outcome::Passed,
+            Some("fail") => Outcome::Failed,
+            Some("skip") => Outcome::Ignored,
+            _ => continue,
+        };
+        results.record(line["Test"].as_str().unwrap(), outcome);
+    }
+    return results;
+}
+
+fn parse_junit(output: &str) -> TestResults {
+    let testcase = Regex::new(r"(?s)<testcase\b([^>]*?)(/>|>(.*?)</testcase>)").unwrap();
+    let attr = |attrs: &str, name: &str| -> Option<String> {
+        let re = Regex::new(&format!(r#"\b{}="([^"]*)""#, name)).unwrap();
+        return re.captures(attrs).map(|c| c[1].to_string());
+    };
+
+    let mut results = TestResults::default();
+    for case in testcase.captures_iter(output) {
+        let name = attr(&case[1], "name").unwrap_or_default();
+        let name = match attr(&case[1], "classname") {
+            Some(class) => format!("{}.{}", class, name),
+            None => name,
+        };
+        let body = case.get(3).map(|m| m.as_str()).unwrap_or("");
+        let outcome = if body.contains("<failure") || body.contains("<error") {
+            Outcome::Failed
+        } else if body.contains("<skipped") {
+            Outcome::Ignored
+        } else {
+            Outcome::Passed
+        };
+        results.record(&name, outcome);
+    }
+    return results;
+}
+
+fn parse_cargo(output: &str) -> TestResults {
+    let test = Regex::new(r"(?m)^test (\S+)(?: - .*)? \.\.\. (ok|FAILED|ignored)").unwrap();
+    let mut results = TestResults::default();
+    for line in test.captures_iter(output) {
+        let outcome = match &line[2] {
+            "ok" => Outcome::Passed,
+            "FAILED" => Outcome::Failed,
+            _ => Outcome::Ignored,
+        };
+        results.record(&line[1], outcome);
+    }
+    return results;
+}
+
+fn parse_pytest(output: &str) -> TestResults {
+    let summary = Regex::new(r"(?m)^=+ (.*\d+ (?:passed|failed).*) in [\d.]+s.* =+$").unwrap();
+    let count = Regex::new(r"(\d+) (passed|failed|skipped|errors?|xfailed|xpassed)").unwrap();
+    let failure = Regex::new(r"(?m)^(?:FAILED|ERROR) (\S+)").unwrap();
+
+    let mut results = TestResults::default();
+    let line = match summary.captures(output) {
+        Some(c) => c[1].to_string(),
+        None => return results,
+    };
+    for c in count.captures_iter(&line) {
+        let n: u32 = c[1].parse().unwrap();
+        match &c[2] {
+            "passed" | "xfailed" => results.passed += n,
+            "failed" | "error" | "errors" | "xpassed" => results.failed += n,
+            _ => results.ignored += n,
+        }
+    }
+    results.failures = failure
+        .captures_iter(output)
+        .map(|c| c[1].to_string())
+        .collect();
+    return results;
+}
+
+fn parse_tap(output: &str) -> TestResults {
+    let plan = Regex::new(r"(?m)^(TAP version \d+|1\.\.\d+)\s*$").unwrap();
+    let test = Regex::new(r"(?m)^(not )?ok\b(?:\s+\d+)?(?:\s*-)?\s*([^#\n]*)(#\s*(\w+))?").unwrap();
+    let mut results = TestResults::default();
+    if !plan.is_match(output) {
+        return results;
+    }
+    for line in test.captures_iter(output) {
+        let directive = line.get(4).map(|m| m.as_str().to_uppercase());
+        let outcome = match (line.get(1).is_some(), directive.as_deref()) {
+            (_, Some("SKIP")) => Outcome::Ignored,
+            // Expected to fail
+            (true, Some("TODO")) => Outcome::Ignored,
+            (true, _) => Outcome::Failed,
+            (false, _) => Outcome::Passed,
+        };
+        results.record(line[2].trim(), outcome);
+    }
+    return results;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results(passed: u32, failed: u32, ignored: u32, failures: &[&str]) -> TestResults {
+        return TestResults {
+            passed,
+            failed,
+            ignored,
+            failures: failures.iter().map(|f| f.to_string()).collect(),
+        };
+    }
+
+    #[test]
+    fn test_cargo() {
+        let output = "\
+running 3 tests
+test a::works ... ok
+test a::broken ... FAILED
+test a::slow ... ignored
+
+test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured; 0 filtered out
+";
+        assert_eq!(parse(output), Some(results(1, 1, 1, &["a::broken"])));
+    }
+
+    #[test]
+    fn test_libtest_json() {
+        let output = r#"{ "type": "suite", "event": "started", "test_count": 2 }
+{ "type": "test", "event": "started", "name": "a" }
+{ "type": "test", "name": "a", "event": "ok" }
+{ "type": "test", "name": "b", "event": "failed", "stdout": "oops" }
+"#;
+        assert_eq!(parse(output), Some(results(1, 1, 0, &["b"])));
+    }
+
+    #[test]
+    fn test_go_json() {
+        let output = r#"{"Action":"run","Package":"p","Test":"TestA"}
+{"Action":"pass","Package":"p","Test":"TestA","Elapsed":0}
+{"Action":"fail","Package":"p","Test":"TestB","Elapsed":0}
+{"Action":"fail","Package":"p","Elapsed":0.1}
+"#;
+        assert_eq!(parse(output), Some(results(1, 1, 0, &["TestB"])));
+    }
+
+    #[test]
+    fn test_junit() {
+        let output = r#"<?xml version="1.0"?>
+<testsuite name="s" tests="3">
+  <testcase classname="Math" name="adds"/>
+  <testcase classname="Math" name="divides"><failure message="boom"/></testcase>
+  <testcase name="later"><skipped/></testcase>
+</testsuite>"#;
+        assert_eq!(parse(output), Some(results(1, 1, 1, &["Math.divides"])));
+    }
+
+    #[test]
+    fn test_pytest() {
+        let output = "\
+tests/test_a.py .F.s
+FAILED tests/test_a.py::test_b - assert 1 == 2
+============ 1 failed, 2 passed, 1 skipped in 0.12s ============
+";
+        assert_eq!(
+            parse(output),
+            Some(results(2, 1, 1, &["tests/test_a.py::test_b"]))
+        );
+    }
+
+    #[test]
+    fn test_tap() {
+        let output = "\
+TAP version 13
+1..3
+ok 1 - adds
+not ok 2 - divides
+ok 3 - later # SKIP not yet
+";
+        assert_eq!(parse(output), Some(results(1, 1, 1, &["divides"])));
+    }
+
+    #[test]
+    fn test_unknown() {
+        assert_eq!(parse("make: *** [all] Error 1"), None);
+        assert_eq!(results(3, 1, 0, &[]).to_string(), "3 passed, 1 failed");
+    }
+}
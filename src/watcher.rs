@@ -0,0 +1,40 @@
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+
+use crate::executor::{Cancel, Event};
+use crate::ignore::Checker;
+use crate::orchestrator::FileChangeEvent;
+
+// Watches `root`, sending changes to the executor from notify's thread. With
+// `restart`, a change that isn't ignored cancels the build or tests that are
+// running, since they're checking code that's out of date.
+pub fn watch<T: Send + 'static>(
+    root: &Path,
+    tx: Sender<Event<T>>,
+    restart: Option<(Checker, Cancel)>,
+) -> notify::Result<RecommendedWatcher> {
+    let restart = restart.map(Mutex::new);
+    // TODO(dmiller): uhh this doesn't actually watch recursively on WSL?
+    let mut watcher: RecommendedWatcher =
+        Watcher::new_immediate(move |res: notify::Result<notify::Event>| {
+            let event = match res {
+                Ok(event) => FileChangeEvent::new(event),
+                Err(e) => {
+                    let _ = tx.send(Event::WatchFailed(e.to_string()));
+                    return;
+                }
+            };
+            if let Some(restart) = &restart {
+                let (checker, cancel) = &mut *restart.lock().unwrap();
+                if !checker.is_ignored(event.clone()) {
+                    cancel.cancel();
+                }
+            }
+            // Fails once we've stopped listening, which is fine
+            let _ = tx.send(Event::Change(vec![event]));
+        })?;
+    watcher.watch(root, RecursiveMode::Recursive)?;
+    return Ok(watcher);
+}